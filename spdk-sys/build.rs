@@ -42,6 +42,8 @@ fn main() {
     // the resulting bindings.
     let bindings = bindgen::Builder::default()
         .clang_arg(format!("-I{}", src.join("build/include").display()))
+        // for the headers of bdev modules, e.g. "module/bdev/malloc/bdev_malloc.h"
+        .clang_arg(format!("-I{}", src.display()))
         // The input header we would like to generate bindings for.
         .header("wrapper.h")
         .parse_callbacks(Box::new(ignored_macros))
@@ -1,4 +1,5 @@
 #include "spdk/bdev.h"
+#include "spdk/bdev_module.h"
 #include "spdk/blob.h"
 #include "spdk/blob_bdev.h"
 #include "spdk/env.h"
@@ -8,3 +9,6 @@
 #include "spdk/vmd.h"
 #include "spdk/log.h"
 #include "spdk/blobfs.h"
+#include "module/bdev/aio/bdev_aio.h"
+#include "module/bdev/malloc/bdev_malloc.h"
+#include "module/bdev/null/bdev_null.h"
@@ -1,13 +1,24 @@
 //! BDev wrapper
 
 use crate::complete::LocalComplete;
-use crate::{blob::IoChannel, Result, SpdkError};
+use crate::{blob::IoChannel, IoStatus, NvmeStatus, Result, ScsiStatus, SpdkError};
 use log::*;
+use serde::{Deserialize, Serialize};
 use spdk_sys::*;
 
+use futures_lite::Stream;
 use std::{
-    ffi::{c_void, CString},
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    ffi::{c_void, CStr, CString},
+    fmt,
+    marker::PhantomData,
     mem::MaybeUninit,
+    os::raw::c_int,
+    pin::Pin,
+    rc::{Rc, Weak},
+    sync::atomic::{AtomicPtr, Ordering},
+    task::{Context, Poll, Waker},
 };
 use std::{
     ops::{Deref, DerefMut},
@@ -18,7 +29,7 @@ use std::{
 /// TODO: Implement Drop
 #[derive(Debug)]
 pub struct BDev {
-    ptr: *mut spdk_bdev,
+    pub(crate) ptr: *mut spdk_bdev,
 }
 
 impl BDev {
@@ -42,6 +53,149 @@ impl BDev {
         ret
     }
 
+    /// Get the number of blocks of the block device.
+    pub fn get_num_blocks(&self) -> u64 {
+        unsafe { spdk_bdev_get_num_blocks(self.ptr) }
+    }
+
+    /// Iterate over all registered block devices.
+    pub fn iter_all() -> BdevIter {
+        BdevIter {
+            next: unsafe { spdk_bdev_first() },
+            leaf: false,
+        }
+    }
+
+    /// Iterate over registered block devices that are not claimed by any module.
+    pub fn iter_leaf() -> BdevIter {
+        BdevIter {
+            next: unsafe { spdk_bdev_first_leaf() },
+            leaf: true,
+        }
+    }
+
+    pub fn get_name(&self) -> String {
+        unsafe { CStr::from_ptr(spdk_bdev_get_name(self.ptr)) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    pub fn get_product_name(&self) -> String {
+        unsafe { CStr::from_ptr(spdk_bdev_get_product_name(self.ptr)) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Get the aliases of the block device.
+    pub fn get_aliases(&self) -> Vec<String> {
+        let mut aliases = vec![];
+        unsafe {
+            let list = spdk_bdev_get_aliases(self.ptr);
+            let mut alias = (*list).tqh_first;
+            while !alias.is_null() {
+                let name = CStr::from_ptr((*alias).alias.name);
+                aliases.push(name.to_string_lossy().into_owned());
+                alias = (*alias).tailq.tqe_next;
+            }
+        }
+        aliases
+    }
+
+    /// Get the UUID of the block device in lowercase string form.
+    pub fn get_uuid(&self) -> String {
+        let mut buf = [0u8; SPDK_UUID_STRING_LEN as usize];
+        unsafe {
+            spdk_uuid_fmt_lower(
+                buf.as_mut_ptr() as _,
+                buf.len() as _,
+                spdk_bdev_get_uuid(self.ptr),
+            );
+        }
+        CStr::from_bytes_until_nul(&buf)
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// Get the size of the metadata per block in bytes, 0 if there is none.
+    pub fn get_md_size(&self) -> u32 {
+        unsafe { spdk_bdev_get_md_size(self.ptr) }
+    }
+
+    /// Whether metadata is interleaved with block data.
+    pub fn is_md_interleaved(&self) -> bool {
+        unsafe { spdk_bdev_is_md_interleaved(self.ptr) }
+    }
+
+    #[allow(non_upper_case_globals)]
+    pub fn get_dif_type(&self) -> DifType {
+        match unsafe { spdk_bdev_get_dif_type(self.ptr) } {
+            spdk_dif_type_SPDK_DIF_TYPE1 => DifType::Type1,
+            spdk_dif_type_SPDK_DIF_TYPE2 => DifType::Type2,
+            spdk_dif_type_SPDK_DIF_TYPE3 => DifType::Type3,
+            _ => DifType::Disable,
+        }
+    }
+
+    /// Get the minimum number of blocks a write must be made of.
+    pub fn get_write_unit_size(&self) -> u32 {
+        unsafe { spdk_bdev_get_write_unit_size(self.ptr) }
+    }
+
+    /// Get the optimal I/O boundary in blocks, 0 if there is none.
+    pub fn get_optimal_io_boundary(&self) -> u32 {
+        unsafe { spdk_bdev_get_optimal_io_boundary(self.ptr) }
+    }
+
+    /// Get the maximum size of a single I/O in bytes, `None` if unlimited.
+    pub fn get_max_transfer_size(&self) -> Option<u64> {
+        let (segment_size, num_segments) =
+            unsafe { ((*self.ptr).max_segment_size, (*self.ptr).max_num_segments) };
+        if segment_size == 0 || num_segments == 0 {
+            return None;
+        }
+        Some(segment_size as u64 * num_segments as u64)
+    }
+
+    /// Whether the block device has a volatile write cache.
+    pub fn has_write_cache(&self) -> bool {
+        unsafe { spdk_bdev_has_write_cache(self.ptr) }
+    }
+
+    /// Whether the block device supports the given I/O type.
+    pub fn io_type_supported(&self, io_type: BdevIoType) -> bool {
+        unsafe { spdk_bdev_io_type_supported(self.ptr, io_type as spdk_bdev_io_type) }
+    }
+
+    /// Get all I/O types supported by the block device.
+    pub fn get_supported_io_types(&self) -> Vec<BdevIoType> {
+        BdevIoType::ALL
+            .iter()
+            .copied()
+            .filter(|&io_type| self.io_type_supported(io_type))
+            .collect()
+    }
+
+    /// Collect the properties of the block device.
+    pub fn get_info(&self) -> BdevInfo {
+        BdevInfo {
+            name: self.get_name(),
+            aliases: self.get_aliases(),
+            product_name: self.get_product_name(),
+            uuid: self.get_uuid(),
+            block_size: self.get_block_size(),
+            num_blocks: self.get_num_blocks(),
+            buf_align: self.get_buf_align(),
+            md_size: self.get_md_size(),
+            md_interleave: self.is_md_interleaved(),
+            dif_type: self.get_dif_type(),
+            write_unit_size: self.get_write_unit_size(),
+            optimal_io_boundary: self.get_optimal_io_boundary(),
+            max_transfer_size: self.get_max_transfer_size(),
+            write_cache: self.has_write_cache(),
+            supported_io_types: self.get_supported_io_types(),
+        }
+    }
+
     pub fn release_io_channel(&self, ioc: IoChannel) {
         unsafe {
             spdk_put_io_channel(ioc.ptr);
@@ -49,68 +203,525 @@ impl BDev {
     }
 }
 
+/// Parse a UUID string, e.g. `"f8a6b2a0-5a2c-4c3e-9a9a-0123456789ab"`.
+pub(crate) fn parse_uuid(uuid: &str) -> Result<spdk_uuid> {
+    let cuuid = CString::new(uuid).map_err(|_| SpdkError::from(-(EINVAL as i32)))?;
+    let mut raw = MaybeUninit::zeroed();
+    let err = unsafe { spdk_uuid_parse(raw.as_mut_ptr(), cuuid.as_ptr()) };
+    SpdkError::from_retval(err)?;
+    Ok(unsafe { raw.assume_init() })
+}
+
+/// Completion callback of bdev management calls, e.g. deleting a bdev.
+pub(crate) extern "C" fn bdev_op_callback(arg: *mut c_void, bdeverrno: c_int) {
+    let complete = unsafe { &mut *(arg as *mut LocalComplete<Result<()>>) };
+    complete.complete(SpdkError::from_retval(bdeverrno));
+}
+
+/// Start a bdev management call with `bdev_op_callback` and wait for it.
+pub(crate) async fn do_bdev_op(f: impl FnOnce(*mut c_void)) -> Result<()> {
+    let complete = LocalComplete::<Result<()>>::new();
+    futures_lite::pin!(complete);
+    f(complete.as_arg());
+    complete.await
+}
+
+/// Iterator over registered block devices.
+#[derive(Debug)]
+pub struct BdevIter {
+    next: *mut spdk_bdev,
+    leaf: bool,
+}
+
+impl Iterator for BdevIter {
+    type Item = BDev;
+
+    fn next(&mut self) -> Option<BDev> {
+        if self.next.is_null() {
+            return None;
+        }
+        let ptr = self.next;
+        self.next = unsafe {
+            if self.leaf {
+                spdk_bdev_next_leaf(ptr)
+            } else {
+                spdk_bdev_next(ptr)
+            }
+        };
+        Some(BDev { ptr })
+    }
+}
+
+/// Properties of a block device.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BdevInfo {
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub product_name: String,
+    pub uuid: String,
+    pub block_size: u32,
+    pub num_blocks: u64,
+    pub buf_align: usize,
+    pub md_size: u32,
+    pub md_interleave: bool,
+    pub dif_type: DifType,
+    pub write_unit_size: u32,
+    pub optimal_io_boundary: u32,
+    pub max_transfer_size: Option<u64>,
+    pub write_cache: bool,
+    pub supported_io_types: Vec<BdevIoType>,
+}
+
+/// Data integrity field type of a block device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DifType {
+    Disable,
+    Type1,
+    Type2,
+    Type3,
+}
+
+/// I/O statistics of a block device or channel.
+///
+/// Counters are cumulative since the bdev was registered.
+/// Latencies are in ticks of `ticks_rate` per second.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BdevIoStat {
+    pub bytes_read: u64,
+    pub num_read_ops: u64,
+    pub bytes_written: u64,
+    pub num_write_ops: u64,
+    pub bytes_unmapped: u64,
+    pub num_unmap_ops: u64,
+    pub read_latency_ticks: u64,
+    pub write_latency_ticks: u64,
+    pub unmap_latency_ticks: u64,
+    pub max_read_latency_ticks: u64,
+    pub max_write_latency_ticks: u64,
+    pub max_unmap_latency_ticks: u64,
+    /// Number of I/Os that completed with an error.
+    pub num_errors: u64,
+    pub ticks_rate: u64,
+}
+
+impl BdevIoStat {
+    /// Get the counters accumulated since `earlier`.
+    ///
+    /// The max latencies are kept as they are, since they cannot be subtracted.
+    pub fn delta(&self, earlier: &BdevIoStat) -> BdevIoStat {
+        BdevIoStat {
+            bytes_read: self.bytes_read.saturating_sub(earlier.bytes_read),
+            num_read_ops: self.num_read_ops.saturating_sub(earlier.num_read_ops),
+            bytes_written: self.bytes_written.saturating_sub(earlier.bytes_written),
+            num_write_ops: self.num_write_ops.saturating_sub(earlier.num_write_ops),
+            bytes_unmapped: self.bytes_unmapped.saturating_sub(earlier.bytes_unmapped),
+            num_unmap_ops: self.num_unmap_ops.saturating_sub(earlier.num_unmap_ops),
+            read_latency_ticks: self
+                .read_latency_ticks
+                .saturating_sub(earlier.read_latency_ticks),
+            write_latency_ticks: self
+                .write_latency_ticks
+                .saturating_sub(earlier.write_latency_ticks),
+            unmap_latency_ticks: self
+                .unmap_latency_ticks
+                .saturating_sub(earlier.unmap_latency_ticks),
+            num_errors: self.num_errors.saturating_sub(earlier.num_errors),
+            ..*self
+        }
+    }
+
+    /// Average read latency in microseconds.
+    pub fn avg_read_latency_us(&self) -> f64 {
+        self.avg_latency_us(self.read_latency_ticks, self.num_read_ops)
+    }
+
+    /// Average write latency in microseconds.
+    pub fn avg_write_latency_us(&self) -> f64 {
+        self.avg_latency_us(self.write_latency_ticks, self.num_write_ops)
+    }
+
+    fn avg_latency_us(&self, ticks: u64, ops: u64) -> f64 {
+        if ops == 0 || self.ticks_rate == 0 {
+            return 0.0;
+        }
+        ticks as f64 * 1_000_000.0 / self.ticks_rate as f64 / ops as f64
+    }
+}
+
+/// `spdk_bdev_io_stat` with storage for its error counters.
+struct RawIoStat {
+    stat: spdk_bdev_io_stat,
+    io_error: spdk_bdev_io_error_stat,
+}
+
+impl RawIoStat {
+    fn new() -> Box<Self> {
+        let mut raw: Box<Self> = Box::new(unsafe { std::mem::zeroed() });
+        raw.stat.io_error = &mut raw.io_error;
+        raw
+    }
+
+    fn to_stat(&self) -> BdevIoStat {
+        let stat = &self.stat;
+        BdevIoStat {
+            bytes_read: stat.bytes_read,
+            num_read_ops: stat.num_read_ops,
+            bytes_written: stat.bytes_written,
+            num_write_ops: stat.num_write_ops,
+            bytes_unmapped: stat.bytes_unmapped,
+            num_unmap_ops: stat.num_unmap_ops,
+            read_latency_ticks: stat.read_latency_ticks,
+            write_latency_ticks: stat.write_latency_ticks,
+            unmap_latency_ticks: stat.unmap_latency_ticks,
+            max_read_latency_ticks: stat.max_read_latency_ticks,
+            max_write_latency_ticks: stat.max_write_latency_ticks,
+            max_unmap_latency_ticks: stat.max_unmap_latency_ticks,
+            num_errors: self.io_error.error_status.iter().map(|&n| n as u64).sum(),
+            ticks_rate: stat.ticks_rate,
+        }
+    }
+}
+
+/// Options for opening a block device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BdevOpenOpts {
+    write: bool,
+    claim: Option<BdevClaimType>,
+}
+
+impl Default for BdevOpenOpts {
+    fn default() -> Self {
+        Self {
+            write: true,
+            claim: None,
+        }
+    }
+}
+
+impl BdevOpenOpts {
+    /// Default options: read-write without claim.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open the bdev read-only. Writes fail with `EBADF`.
+    pub fn read_only(mut self) -> Self {
+        self.write = false;
+        self
+    }
+
+    /// Open the bdev for read and write.
+    pub fn read_write(mut self) -> Self {
+        self.write = true;
+        self
+    }
+
+    /// Claim the bdev right after it is opened.
+    pub fn claim(mut self, claim: BdevClaimType) -> Self {
+        self.claim = Some(claim);
+        self
+    }
+
+    /// Open the block device with these options.
+    pub fn open(&self, name: &str) -> Result<BdevDesc> {
+        let desc = BdevDesc::open(name, self.write)?;
+        if let Some(claim) = self.claim {
+            if let Err(e) = desc.claim(claim) {
+                desc.close();
+                return Err(e);
+            }
+        }
+        Ok(desc)
+    }
+}
+
+/// Claim on a block device, restricting how other descriptors may use it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BdevClaimType {
+    /// Only the claiming descriptor may write. Requires a read-write descriptor.
+    ExclusiveWrite,
+    /// Any number of readers, only the claiming descriptor may write.
+    /// Requires a read-write descriptor.
+    ReadManyWriteOne,
+    /// Any number of readers, no writers. Requires a read-only descriptor.
+    ReadManyWriteNone,
+    /// Any number of readers, writers must claim with the same key.
+    ReadManyWriteShared(u64),
+}
+
+impl BdevClaimType {
+    fn as_raw(&self) -> spdk_bdev_claim_type {
+        match self {
+            BdevClaimType::ExclusiveWrite => spdk_bdev_claim_type_SPDK_BDEV_CLAIM_EXCL_WRITE,
+            BdevClaimType::ReadManyWriteOne => {
+                spdk_bdev_claim_type_SPDK_BDEV_CLAIM_READ_MANY_WRITE_ONE
+            }
+            BdevClaimType::ReadManyWriteNone => {
+                spdk_bdev_claim_type_SPDK_BDEV_CLAIM_READ_MANY_WRITE_NONE
+            }
+            BdevClaimType::ReadManyWriteShared(_) => {
+                spdk_bdev_claim_type_SPDK_BDEV_CLAIM_READ_MANY_WRITE_SHARED
+            }
+        }
+    }
+}
+
+/// The module that claims taken through `BdevDesc::claim` are recorded under.
+fn claim_module() -> *mut spdk_bdev_module {
+    static MODULE: AtomicPtr<spdk_bdev_module> = AtomicPtr::new(std::ptr::null_mut());
+    let ptr = MODULE.load(Ordering::Acquire);
+    if !ptr.is_null() {
+        return ptr;
+    }
+    let mut module: spdk_bdev_module = unsafe { std::mem::zeroed() };
+    module.name = b"async_spdk\0".as_ptr() as _;
+    let new = Box::into_raw(Box::new(module));
+    match MODULE.compare_exchange(
+        std::ptr::null_mut(),
+        new,
+        Ordering::AcqRel,
+        Ordering::Acquire,
+    ) {
+        Ok(_) => new,
+        Err(ptr) => {
+            unsafe { drop(Box::from_raw(new)) };
+            ptr
+        }
+    }
+}
+
+/// Type of I/O that a block device may support.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[repr(u32)]
+pub enum BdevIoType {
+    Read = spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_READ,
+    Write = spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_WRITE,
+    Unmap = spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_UNMAP,
+    Flush = spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_FLUSH,
+    Reset = spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_RESET,
+    NvmeAdmin = spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_NVME_ADMIN,
+    NvmeIo = spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_NVME_IO,
+    NvmeIoMd = spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_NVME_IO_MD,
+    WriteZeroes = spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_WRITE_ZEROES,
+    Zcopy = spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_ZCOPY,
+    GetZoneInfo = spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_GET_ZONE_INFO,
+    ZoneManagement = spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_ZONE_MANAGEMENT,
+    ZoneAppend = spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_ZONE_APPEND,
+    Compare = spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_COMPARE,
+    CompareAndWrite = spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_COMPARE_AND_WRITE,
+    Abort = spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_ABORT,
+}
+
+impl BdevIoType {
+    pub(crate) fn from_raw(io_type: spdk_bdev_io_type) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|&t| t as spdk_bdev_io_type == io_type)
+    }
+
+    pub const ALL: [BdevIoType; 16] = [
+        BdevIoType::Read,
+        BdevIoType::Write,
+        BdevIoType::Unmap,
+        BdevIoType::Flush,
+        BdevIoType::Reset,
+        BdevIoType::NvmeAdmin,
+        BdevIoType::NvmeIo,
+        BdevIoType::NvmeIoMd,
+        BdevIoType::WriteZeroes,
+        BdevIoType::Zcopy,
+        BdevIoType::GetZoneInfo,
+        BdevIoType::ZoneManagement,
+        BdevIoType::ZoneAppend,
+        BdevIoType::Compare,
+        BdevIoType::CompareAndWrite,
+        BdevIoType::Abort,
+    ];
+}
+
 /// Bdev
 #[derive(Debug)]
 pub struct BdevDesc {
     ptr: *mut spdk_bdev_desc,
+    events: Rc<BdevEventCtx>,
+}
+
+impl Drop for BdevDesc {
+    /// Close the descriptor, releasing the event context held by SPDK.
+    fn drop(&mut self) {
+        self.close();
+    }
 }
 
 impl BdevDesc {
+    /// Open a block device for read and write.
+    ///
+    /// If the bdev is hot-removed, the descriptor is closed automatically
+    /// and further operations fail with `ENODEV`.
     pub fn create_desc(name: &str) -> Result<Self> {
-        let cname = CString::new(name).expect("Could not parse to CString");
+        BdevOpenOpts::new().open(name)
+    }
+
+    fn open(name: &str, write: bool) -> Result<Self> {
+        let cname = CString::new(name).map_err(|_| SpdkError::from(-(EINVAL as i32)))?;
         let mut ptr = MaybeUninit::uninit();
-        extern "C" fn callback(
-            ty: spdk_bdev_event_type,
-            bdev: *mut spdk_bdev,
-            event_ctx: *mut c_void,
-        ) {
-            warn!(
-                "bdev callback: type = {:?}, bdev={:?}, ctx={:?}",
-                ty, bdev, event_ctx
-            );
-        }
+        let events = BdevEventCtx::new();
+        // the reference passed to SPDK is released when the descriptor is closed
+        let event_ctx = Rc::into_raw(events.clone());
         let err = unsafe {
             spdk_bdev_open_ext(
                 cname.as_ptr(),
-                true,
-                Some(callback),
-                std::ptr::null_mut(),
+                write,
+                Some(bdev_event_callback),
+                event_ctx as _,
                 ptr.as_mut_ptr(),
             )
         };
+        if let Err(e) = SpdkError::from_retval(err) {
+            unsafe { drop(Rc::from_raw(event_ctx)) };
+            return Err(e.with_op("open bdev").with_object(name));
+        }
+        let ptr = unsafe { ptr.assume_init() };
+        events.desc.set(ptr);
+        Ok(BdevDesc { ptr, events })
+    }
+
+    /// Subscribe to events of the opened bdev.
+    ///
+    /// The stream ends after the bdev is removed or the descriptor is closed.
+    pub fn events(&self) -> BdevEventStream {
+        self.events.subscribe()
+    }
+
+    /// Set a handler called on every event of the opened bdev.
+    ///
+    /// It runs before the descriptor is closed on hot-remove.
+    pub fn set_event_handler(&self, handler: impl FnMut(BdevEvent) + 'static) {
+        self.events.set_handler(Box::new(handler));
+    }
+
+    /// Whether the bdev has been hot-removed.
+    pub fn is_removed(&self) -> bool {
+        self.events.removed.get()
+    }
+
+    /// Claim the bdev for this descriptor.
+    ///
+    /// Fails with `EPERM` if another module holds a conflicting claim.
+    pub fn claim(&self, claim: BdevClaimType) -> Result<()> {
+        self.check_open()?;
+        let module = claim_module();
+        let err = match claim {
+            BdevClaimType::ExclusiveWrite => unsafe {
+                spdk_bdev_module_claim_bdev(self.get_bdev()?.ptr, self.ptr, module)
+            },
+            _ => unsafe {
+                let mut opts = MaybeUninit::uninit();
+                spdk_bdev_claim_opts_init(
+                    opts.as_mut_ptr(),
+                    std::mem::size_of::<spdk_bdev_claim_opts>() as _,
+                );
+                let mut opts = opts.assume_init();
+                if let BdevClaimType::ReadManyWriteShared(key) = claim {
+                    opts.shared_claim_key = key;
+                }
+                spdk_bdev_module_claim_bdev_desc(self.ptr, claim.as_raw(), &mut opts, module)
+            },
+        };
         SpdkError::from_retval(err)?;
-        Ok(BdevDesc {
-            ptr: unsafe { ptr.assume_init() },
-        })
+        self.events.claim.set(Some(claim));
+        Ok(())
+    }
+
+    /// Release the claim taken by `claim`.
+    ///
+    /// Only `ExclusiveWrite` can be released while the descriptor is open,
+    /// other claims are released when the descriptor is closed.
+    pub fn release_claim(&self) -> Result<()> {
+        self.check_open()?;
+        match self.events.claim.get() {
+            None => Ok(()),
+            Some(BdevClaimType::ExclusiveWrite) => {
+                unsafe { spdk_bdev_module_release_bdev(self.get_bdev()?.ptr) };
+                self.events.claim.set(None);
+                Ok(())
+            }
+            Some(claim) => Err(SpdkError::unsupported(&format!(
+                "releasing {:?} claim before close",
+                claim
+            ))),
+        }
+    }
+
+    /// Get the claim held by this descriptor.
+    pub fn get_claim(&self) -> Option<BdevClaimType> {
+        self.events.claim.get()
+    }
+
+    /// Get the I/O statistics of the bdev, summed over all channels.
+    pub async fn stats(&self) -> Result<BdevIoStat> {
+        let bdev = self.get_bdev()?;
+        let mut raw = RawIoStat::new();
+        let complete = LocalComplete::<Result<()>>::new();
+        futures_lite::pin!(complete);
+        unsafe {
+            spdk_bdev_get_device_stat(
+                bdev.ptr,
+                &mut raw.stat,
+                spdk_bdev_reset_stat_mode_SPDK_BDEV_RESET_STAT_NONE,
+                Some(stat_callback),
+                complete.as_arg(),
+            );
+        }
+        complete.await?;
+        Ok(raw.to_stat())
+    }
+
+    /// Get the I/O statistics of one channel.
+    ///
+    /// Must be called on the thread that owns `io_channel`.
+    pub fn channel_stats(&self, io_channel: &IoChannel) -> Result<BdevIoStat> {
+        let bdev = self.get_bdev()?;
+        let mut raw = RawIoStat::new();
+        unsafe { spdk_bdev_get_io_stat(bdev.ptr, io_channel.ptr, &mut raw.stat) };
+        Ok(raw.to_stat())
+    }
+
+    /// Fail if the descriptor has been closed.
+    fn check_open(&self) -> Result<()> {
+        if self.events.closed.get() {
+            return Err(SpdkError::from(-(ENODEV as i32)));
+        }
+        Ok(())
     }
 
     pub fn get_bdev(&self) -> Result<BDev> {
+        self.check_open()?;
         let ptr = unsafe { spdk_bdev_desc_get_bdev(self.ptr) };
         if ptr.is_null() {
-            return Err(SpdkError::from(-1));
+            return Err(SpdkError::from(-(ENODEV as i32)).with_op("get bdev"));
         }
         Ok(BDev { ptr })
     }
 
     pub fn get_io_channel(&self) -> Result<IoChannel> {
+        self.check_open()?;
         let ptr = unsafe { spdk_bdev_get_io_channel(self.ptr) };
         if ptr.is_null() {
-            return Err(SpdkError::from(-1));
+            return Err(SpdkError::from(-(ENOMEM as i32)).with_op("get io channel"));
         }
         Ok(IoChannel { ptr })
     }
 
+    /// Close the descriptor. Does nothing if it is already closed.
     pub fn close(&self) {
-        unsafe {
-            spdk_bdev_close(self.ptr);
-        }
+        unsafe { BdevEventCtx::close_desc(Rc::as_ptr(&self.events)) };
     }
 
     /// write data at offset
     ///
-    /// TODO: check write buffer size and handle return value
-    ///
-    /// spdk_bdev_write return 0 for success
+    /// TODO: check write buffer size
     pub async fn write(
         &self,
         io_channel: &IoChannel,
@@ -118,7 +729,7 @@ impl BdevDesc {
         length: u64,
         buf: &[u8],
     ) -> Result<()> {
-        do_async(|arg| unsafe {
+        self.do_io("write", io_channel, |arg| unsafe {
             spdk_bdev_write(
                 self.ptr,
                 io_channel.ptr,
@@ -127,16 +738,12 @@ impl BdevDesc {
                 length,
                 Some(callback),
                 arg,
-            );
+            )
         })
         .await
     }
 
     /// read data at offset
-    ///
-    /// TODO: handle return value (should not be ())
-    ///
-    /// spdk_bdev_read return 0 for success
     pub async fn read(
         &self,
         io_channel: &IoChannel,
@@ -144,7 +751,7 @@ impl BdevDesc {
         length: u64,
         buf: &mut [u8],
     ) -> Result<()> {
-        do_async(|arg| unsafe {
+        self.do_io("read", io_channel, |arg| unsafe {
             spdk_bdev_read(
                 self.ptr,
                 io_channel.ptr,
@@ -153,16 +760,631 @@ impl BdevDesc {
                 length,
                 Some(callback),
                 arg,
-            );
+            )
+        })
+        .await
+    }
+
+    /// read data at offset into a scatter-gather list
+    ///
+    /// `offset` and `length` must be multiples of the block size.
+    pub async fn readv(
+        &self,
+        io_channel: &IoChannel,
+        offset: u64,
+        length: u64,
+        iov: &mut IoVec<'_>,
+    ) -> Result<()> {
+        let (offset_blocks, num_blocks) = self.bytes_to_blocks(offset, length)?;
+        iov.check_writable()?;
+        self.check_iov(offset_blocks, num_blocks, iov)?;
+        self.do_io("read", io_channel, |arg| unsafe {
+            spdk_bdev_readv(
+                self.ptr,
+                io_channel.ptr,
+                iov.as_mut_ptr(),
+                iov.iovcnt(),
+                offset,
+                length,
+                Some(callback),
+                arg,
+            )
         })
         .await
     }
+
+    /// read `num_blocks` blocks at `offset_blocks` into a scatter-gather list
+    pub async fn readv_blocks(
+        &self,
+        io_channel: &IoChannel,
+        offset_blocks: u64,
+        num_blocks: u64,
+        iov: &mut IoVec<'_>,
+    ) -> Result<()> {
+        iov.check_writable()?;
+        self.check_iov(offset_blocks, num_blocks, iov)?;
+        self.do_io("read", io_channel, |arg| unsafe {
+            spdk_bdev_readv_blocks(
+                self.ptr,
+                io_channel.ptr,
+                iov.as_mut_ptr(),
+                iov.iovcnt(),
+                offset_blocks,
+                num_blocks,
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+    }
+
+    /// write data from a scatter-gather list at offset
+    ///
+    /// `offset` and `length` must be multiples of the block size.
+    pub async fn writev(
+        &self,
+        io_channel: &IoChannel,
+        offset: u64,
+        length: u64,
+        iov: &IoVec<'_>,
+    ) -> Result<()> {
+        let (offset_blocks, num_blocks) = self.bytes_to_blocks(offset, length)?;
+        self.check_iov(offset_blocks, num_blocks, iov)?;
+        self.do_io("write", io_channel, |arg| unsafe {
+            spdk_bdev_writev(
+                self.ptr,
+                io_channel.ptr,
+                iov.as_ptr() as _,
+                iov.iovcnt(),
+                offset,
+                length,
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+    }
+
+    /// write `num_blocks` blocks from a scatter-gather list at `offset_blocks`
+    pub async fn writev_blocks(
+        &self,
+        io_channel: &IoChannel,
+        offset_blocks: u64,
+        num_blocks: u64,
+        iov: &IoVec<'_>,
+    ) -> Result<()> {
+        self.check_iov(offset_blocks, num_blocks, iov)?;
+        self.do_io("write", io_channel, |arg| unsafe {
+            spdk_bdev_writev_blocks(
+                self.ptr,
+                io_channel.ptr,
+                iov.as_ptr() as _,
+                iov.iovcnt(),
+                offset_blocks,
+                num_blocks,
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+    }
+
+    /// Release the blocks at offset, e.g. TRIM on an SSD.
+    ///
+    /// `offset` and `length` must be multiples of the block size.
+    pub async fn unmap(&self, io_channel: &IoChannel, offset: u64, length: u64) -> Result<()> {
+        self.check_supported(BdevIoType::Unmap)?;
+        self.do_io("unmap", io_channel, |arg| unsafe {
+            spdk_bdev_unmap(
+                self.ptr,
+                io_channel.ptr,
+                offset,
+                length,
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+    }
+
+    /// Release `num_blocks` blocks at `offset_blocks`.
+    pub async fn unmap_blocks(
+        &self,
+        io_channel: &IoChannel,
+        offset_blocks: u64,
+        num_blocks: u64,
+    ) -> Result<()> {
+        self.check_supported(BdevIoType::Unmap)?;
+        self.do_io("unmap", io_channel, |arg| unsafe {
+            spdk_bdev_unmap_blocks(
+                self.ptr,
+                io_channel.ptr,
+                offset_blocks,
+                num_blocks,
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+    }
+
+    /// Write zeros at offset without transferring a data buffer.
+    ///
+    /// `offset` and `length` must be multiples of the block size.
+    pub async fn write_zeroes(
+        &self,
+        io_channel: &IoChannel,
+        offset: u64,
+        length: u64,
+    ) -> Result<()> {
+        self.check_supported(BdevIoType::WriteZeroes)?;
+        self.do_io("write zeroes", io_channel, |arg| unsafe {
+            spdk_bdev_write_zeroes(
+                self.ptr,
+                io_channel.ptr,
+                offset,
+                length,
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+    }
+
+    /// Write zeros to `num_blocks` blocks at `offset_blocks`.
+    pub async fn write_zeroes_blocks(
+        &self,
+        io_channel: &IoChannel,
+        offset_blocks: u64,
+        num_blocks: u64,
+    ) -> Result<()> {
+        self.check_supported(BdevIoType::WriteZeroes)?;
+        self.do_io("write zeroes", io_channel, |arg| unsafe {
+            spdk_bdev_write_zeroes_blocks(
+                self.ptr,
+                io_channel.ptr,
+                offset_blocks,
+                num_blocks,
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+    }
+
+    /// Flush the volatile write cache for the given range.
+    ///
+    /// `offset` and `length` must be multiples of the block size.
+    pub async fn flush(&self, io_channel: &IoChannel, offset: u64, length: u64) -> Result<()> {
+        self.check_supported(BdevIoType::Flush)?;
+        self.do_io("flush", io_channel, |arg| unsafe {
+            spdk_bdev_flush(
+                self.ptr,
+                io_channel.ptr,
+                offset,
+                length,
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+    }
+
+    /// Flush the volatile write cache for `num_blocks` blocks at `offset_blocks`.
+    pub async fn flush_blocks(
+        &self,
+        io_channel: &IoChannel,
+        offset_blocks: u64,
+        num_blocks: u64,
+    ) -> Result<()> {
+        self.check_supported(BdevIoType::Flush)?;
+        self.do_io("flush", io_channel, |arg| unsafe {
+            spdk_bdev_flush_blocks(
+                self.ptr,
+                io_channel.ptr,
+                offset_blocks,
+                num_blocks,
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+    }
+
+    /// Reset the block device.
+    ///
+    /// Outstanding I/O on all channels is aborted before the reset completes.
+    pub async fn reset(&self, io_channel: &IoChannel) -> Result<()> {
+        self.check_supported(BdevIoType::Reset)?;
+        self.do_io("reset", io_channel, |arg| unsafe {
+            spdk_bdev_reset(self.ptr, io_channel.ptr, Some(callback), arg)
+        })
+        .await
+    }
+
+    /// Fail with an unsupported error if the bdev lacks `io_type`.
+    fn check_supported(&self, io_type: BdevIoType) -> Result<()> {
+        if !self.get_bdev()?.io_type_supported(io_type) {
+            return Err(SpdkError::unsupported(&format!("{:?}", io_type)));
+        }
+        Ok(())
+    }
+
+    /// Submit an I/O and wait for its completion.
+    ///
+    /// `submit` is called with the completion argument and returns the submit-time
+    /// error code. If the bdev_io pool is exhausted (-ENOMEM), the I/O is parked on
+    /// the channel with `spdk_bdev_queue_io_wait` and resubmitted once a bdev_io is freed.
+    /// Errors are tagged with `op` and the bdev name.
+    async fn do_io(
+        &self,
+        op: &str,
+        io_channel: &IoChannel,
+        mut submit: impl FnMut(*mut c_void) -> c_int,
+    ) -> Result<()> {
+        let complete = LocalComplete::<Result<()>>::new();
+        futures_lite::pin!(complete);
+        let result = async {
+            loop {
+                self.check_open()?;
+                let rc = submit(complete.as_arg());
+                if rc == 0 {
+                    return complete.as_mut().await;
+                }
+                if rc != -(ENOMEM as i32) {
+                    return Err(SpdkError::from(rc));
+                }
+                IoWaitEntry::wait(&self.get_bdev()?, io_channel).await?;
+            }
+        }
+        .await;
+        result.map_err(|e| match self.get_bdev() {
+            Ok(bdev) => e.with_op(op).with_object(bdev.get_name()),
+            Err(_) => e.with_op(op),
+        })
+    }
+
+    /// Convert a byte range into a block range, failing if it is not block aligned.
+    fn bytes_to_blocks(&self, offset: u64, length: u64) -> Result<(u64, u64)> {
+        let block_size = self.get_bdev()?.get_block_size() as u64;
+        if !offset.is_multiple_of(block_size) || !length.is_multiple_of(block_size) {
+            return Err(SpdkError::from(-(EINVAL as i32)));
+        }
+        Ok((offset / block_size, length / block_size))
+    }
+
+    /// Check that the block range lies within the bdev and that `iov` can hold it.
+    fn check_iov(&self, offset_blocks: u64, num_blocks: u64, iov: &IoVec<'_>) -> Result<()> {
+        let bdev = self.get_bdev()?;
+        let block_size = bdev.get_block_size() as u64;
+        let end = offset_blocks.checked_add(num_blocks);
+        if iov.is_empty()
+            || num_blocks == 0
+            || end.is_none_or(|end| end > bdev.get_num_blocks())
+            || iov.len() < num_blocks * block_size
+        {
+            return Err(SpdkError::from(-(EINVAL as i32)));
+        }
+        Ok(())
+    }
 }
 
-#[warn(dead_code)]
-#[derive(Debug)]
-pub struct IoWaitEntry {
+/// Scatter-gather list for vectored I/O.
+///
+/// Every buffer must be DMA-able memory, e.g. `env::DmaBuf`,
+/// and is borrowed for as long as the list lives. A list holding
+/// shared buffers can only be written, reading into it fails.
+#[derive(Debug, Default)]
+pub struct IoVec<'a> {
+    iovs: Vec<iovec>,
+    /// Whether a shared buffer was pushed.
+    read_only: bool,
+    _marker: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> IoVec<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a buffer to the list.
+    pub fn push(&mut self, buf: &'a mut [u8]) {
+        self.iovs.push(iovec {
+            iov_base: buf.as_mut_ptr() as _,
+            iov_len: buf.len() as _,
+        });
+    }
+
+    /// Append a shared buffer, for lists that are only written.
+    pub fn push_ref(&mut self, buf: &'a [u8]) {
+        self.read_only = true;
+        self.iovs.push(iovec {
+            iov_base: buf.as_ptr() as _,
+            iov_len: buf.len() as _,
+        });
+    }
+
+    /// Total length of all buffers in bytes.
+    pub fn len(&self) -> u64 {
+        self.iovs.iter().map(|iov| iov.iov_len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Fail if the list holds shared buffers, before reading into it.
+    pub(crate) fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(SpdkError::from(-(EINVAL as i32)).with_op("read into shared buffers"));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn iovcnt(&self) -> c_int {
+        self.iovs.len() as c_int
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const iovec {
+        self.iovs.as_ptr()
+    }
+
+    pub(crate) fn as_mut_ptr(&mut self) -> *mut iovec {
+        self.iovs.as_mut_ptr()
+    }
+}
+
+impl<'a> Extend<&'a mut [u8]> for IoVec<'a> {
+    fn extend<I: IntoIterator<Item = &'a mut [u8]>>(&mut self, iter: I) {
+        for buf in iter {
+            self.push(buf);
+        }
+    }
+}
+
+impl<'a> Extend<&'a [u8]> for IoVec<'a> {
+    fn extend<I: IntoIterator<Item = &'a [u8]>>(&mut self, iter: I) {
+        for buf in iter {
+            self.push_ref(buf);
+        }
+    }
+}
+
+/// Event on an opened block device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BdevEvent {
+    /// The bdev is being hot-removed.
+    Remove,
+    /// The bdev has been resized.
+    Resize,
+    /// The bdev reported a media management event.
+    MediaManagement,
+}
+
+impl BdevEvent {
+    #[allow(non_upper_case_globals)]
+    fn from_raw(ty: spdk_bdev_event_type) -> Option<Self> {
+        match ty {
+            spdk_bdev_event_type_SPDK_BDEV_EVENT_REMOVE => Some(BdevEvent::Remove),
+            spdk_bdev_event_type_SPDK_BDEV_EVENT_RESIZE => Some(BdevEvent::Resize),
+            spdk_bdev_event_type_SPDK_BDEV_EVENT_MEDIA_MANAGEMENT => {
+                Some(BdevEvent::MediaManagement)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Stream of events on an opened block device.
+pub struct BdevEventStream {
+    queue: Rc<RefCell<EventQueue>>,
+}
+
+#[derive(Default)]
+struct EventQueue {
+    events: VecDeque<BdevEvent>,
+    waker: Option<Waker>,
+    done: bool,
+}
+
+impl Stream for BdevEventStream {
+    type Item = BdevEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<BdevEvent>> {
+        let mut queue = self.queue.borrow_mut();
+        if let Some(event) = queue.events.pop_front() {
+            return Poll::Ready(Some(event));
+        }
+        if queue.done {
+            return Poll::Ready(None);
+        }
+        queue.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+type EventHandler = Box<dyn FnMut(BdevEvent)>;
+
+/// Event context registered with `spdk_bdev_open_ext` or `spdk_bdev_create_bs_dev_ext`.
+///
+/// On hot-remove, the descriptor (if any) is closed after the event is dispatched.
+/// SPDK delivers events on the thread that opened the bdev, so no locking is needed.
+pub(crate) struct BdevEventCtx {
+    /// Descriptor to close on hot-remove, null if not owned.
+    desc: Cell<*mut spdk_bdev_desc>,
+    removed: Cell<bool>,
+    closed: Cell<bool>,
+    /// Claim held through the descriptor.
+    claim: Cell<Option<BdevClaimType>>,
+    /// Handler used inside the crate, runs before `handler`.
+    internal_handler: RefCell<Option<EventHandler>>,
+    handler: RefCell<Option<EventHandler>>,
+    subscribers: RefCell<Vec<Weak<RefCell<EventQueue>>>>,
+}
+
+impl fmt::Debug for BdevEventCtx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BdevEventCtx")
+            .field("removed", &self.removed.get())
+            .field("closed", &self.closed.get())
+            .finish()
+    }
+}
+
+impl BdevEventCtx {
+    pub(crate) fn new() -> Rc<Self> {
+        Rc::new(BdevEventCtx {
+            desc: Cell::new(std::ptr::null_mut()),
+            removed: Cell::new(false),
+            closed: Cell::new(false),
+            claim: Cell::new(None),
+            internal_handler: RefCell::new(None),
+            handler: RefCell::new(None),
+            subscribers: RefCell::new(vec![]),
+        })
+    }
+
+    pub(crate) fn subscribe(&self) -> BdevEventStream {
+        let queue = Rc::new(RefCell::new(EventQueue {
+            done: self.removed.get() || self.closed.get(),
+            ..Default::default()
+        }));
+        self.subscribers.borrow_mut().push(Rc::downgrade(&queue));
+        BdevEventStream { queue }
+    }
+
+    pub(crate) fn set_handler(&self, handler: EventHandler) {
+        *self.handler.borrow_mut() = Some(handler);
+    }
+
+    pub(crate) fn set_internal_handler(&self, handler: EventHandler) {
+        *self.internal_handler.borrow_mut() = Some(handler);
+    }
+
+    fn dispatch(&self, event: BdevEvent) {
+        if event == BdevEvent::Remove {
+            self.removed.set(true);
+        }
+        // take the handlers out so that they may call back into the descriptor
+        for slot in [&self.internal_handler, &self.handler] {
+            let handler = slot.borrow_mut().take();
+            if let Some(mut handler) = handler {
+                handler(event);
+                slot.borrow_mut().get_or_insert(handler);
+            }
+        }
+        self.subscribers.borrow_mut().retain(|queue| {
+            let queue = match queue.upgrade() {
+                Some(queue) => queue,
+                None => return false,
+            };
+            let mut queue = queue.borrow_mut();
+            queue.events.push_back(event);
+            queue.done |= event == BdevEvent::Remove;
+            if let Some(waker) = queue.waker.take() {
+                waker.wake();
+            }
+            true
+        });
+    }
+
+    /// End all subscriptions.
+    pub(crate) fn finish(&self) {
+        for queue in self.subscribers.borrow_mut().drain(..) {
+            if let Some(queue) = queue.upgrade() {
+                let mut queue = queue.borrow_mut();
+                queue.done = true;
+                if let Some(waker) = queue.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+
+    /// Close the descriptor once and release the reference held by SPDK.
+    ///
+    /// `ctx` may be freed when this returns.
+    unsafe fn close_desc(ctx: *const BdevEventCtx) {
+        let this = &*ctx;
+        if this.closed.replace(true) {
+            return;
+        }
+        // legacy claims outlive the descriptor, the others are released by close
+        if this.claim.take() == Some(BdevClaimType::ExclusiveWrite) {
+            spdk_bdev_module_release_bdev(spdk_bdev_desc_get_bdev(this.desc.get()));
+        }
+        spdk_bdev_close(this.desc.get());
+        this.finish();
+        drop(Rc::from_raw(ctx));
+    }
+}
+
+pub(crate) extern "C" fn bdev_event_callback(
+    ty: spdk_bdev_event_type,
+    bdev: *mut spdk_bdev,
+    event_ctx: *mut c_void,
+) {
+    let event = match BdevEvent::from_raw(ty) {
+        Some(event) => event,
+        None => {
+            warn!("unknown bdev event: type={:?}, bdev={:?}", ty, bdev);
+            return;
+        }
+    };
+    info!("bdev event: {:?}, bdev={:?}", event, bdev);
+    let ctx = event_ctx as *const BdevEventCtx;
+    unsafe {
+        (*ctx).dispatch(event);
+        if event == BdevEvent::Remove && !(*ctx).desc.get().is_null() {
+            BdevEventCtx::close_desc(ctx);
+        }
+    }
+}
+
+/// Entry on a channel's io_wait queue, used while the bdev_io pool is exhausted.
+///
+/// Once queued it is owned by SPDK and freed by `io_wait_callback`,
+/// so the waiting I/O can be dropped while the entry is still queued.
+struct IoWaitEntry {
     wentry: spdk_bdev_io_wait_entry,
+    state: Rc<RefCell<IoWaitState>>,
+}
+
+#[derive(Default)]
+struct IoWaitState {
+    ready: bool,
+    waker: Option<Waker>,
+}
+
+impl IoWaitEntry {
+    /// Wait until a bdev_io becomes available on `io_channel`.
+    async fn wait(bdev: &BDev, io_channel: &IoChannel) -> Result<()> {
+        let state = Rc::new(RefCell::new(IoWaitState::default()));
+        let entry = Box::into_raw(Box::new(IoWaitEntry {
+            wentry: unsafe { std::mem::zeroed() },
+            state: state.clone(),
+        }));
+        let err = unsafe {
+            (*entry).wentry.bdev = bdev.ptr;
+            (*entry).wentry.cb_fn = Some(io_wait_callback);
+            (*entry).wentry.cb_arg = entry as _;
+            spdk_bdev_queue_io_wait(bdev.ptr, io_channel.ptr, &mut (*entry).wentry)
+        };
+        if err != 0 {
+            unsafe { drop(Box::from_raw(entry)) };
+            return Err(SpdkError::from(err));
+        }
+        futures_lite::future::poll_fn(|cx| {
+            let mut state = state.borrow_mut();
+            if state.ready {
+                return Poll::Ready(());
+            }
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        })
+        .await;
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -214,7 +1436,7 @@ impl DmaBuf {
         };
 
         if buf.is_null() {
-            Err(SpdkError::from(-1))
+            Err(SpdkError::from(-(ENOMEM as i32)).with_op("allocate dma buffer"))
         } else {
             Ok(DmaBuf {
                 buf,
@@ -259,16 +1481,61 @@ extern "C" fn callback(bio: *mut spdk_bdev_io, s: bool, arg: *mut c_void) {
 extern "C" fn callback_with<T>(arg: *mut c_void, bs: T, s: bool, bio: *mut spdk_bdev_io) {
     let complete = unsafe { &mut *(arg as *mut LocalComplete<Result<T>>) };
 
-    let result = if !s { Err(SpdkError::from(-1)) } else { Ok(bs) };
+    let result = if !s {
+        Err(SpdkError::io_failed(io_status(bio)))
+    } else {
+        Ok(bs)
+    };
     complete.complete(result);
     unsafe {
         spdk_bdev_free_io(bio);
     }
 }
 
-async fn do_async<T: Unpin>(f: impl FnOnce(*mut c_void)) -> Result<T> {
-    let complete = LocalComplete::<Result<T>>::new();
-    futures_lite::pin!(complete);
-    f(complete.as_arg());
-    complete.await
+/// Get the device status of a completed bdev_io.
+fn io_status(bio: *mut spdk_bdev_io) -> IoStatus {
+    let mut status = IoStatus {
+        nvme: NvmeStatus {
+            cdw0: 0,
+            sct: 0,
+            sc: 0,
+        },
+        scsi: ScsiStatus {
+            sc: 0,
+            sk: 0,
+            asc: 0,
+            ascq: 0,
+        },
+    };
+    let (nvme, scsi) = (&mut status.nvme, &mut status.scsi);
+    unsafe {
+        spdk_bdev_io_get_nvme_status(bio, &mut nvme.cdw0, &mut nvme.sct, &mut nvme.sc);
+        spdk_bdev_io_get_scsi_status(
+            bio,
+            &mut scsi.sc,
+            &mut scsi.sk,
+            &mut scsi.asc,
+            &mut scsi.ascq,
+        );
+    }
+    status
+}
+
+extern "C" fn stat_callback(
+    _bdev: *mut spdk_bdev,
+    _stat: *mut spdk_bdev_io_stat,
+    arg: *mut c_void,
+    rc: c_int,
+) {
+    let complete = unsafe { &mut *(arg as *mut LocalComplete<Result<()>>) };
+    complete.complete(SpdkError::from_retval(rc));
+}
+
+extern "C" fn io_wait_callback(arg: *mut c_void) {
+    let entry = unsafe { Box::from_raw(arg as *mut IoWaitEntry) };
+    let mut state = entry.state.borrow_mut();
+    state.ready = true;
+    if let Some(waker) = state.waker.take() {
+        waker.wake();
+    }
 }
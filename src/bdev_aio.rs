@@ -0,0 +1,91 @@
+//! AIO bdev, a block device backed by a file or kernel block device
+
+use crate::bdev::{bdev_op_callback, do_bdev_op, BDev};
+use crate::{Result, SpdkError};
+use spdk_sys::*;
+use std::{ffi::CString, fs::OpenOptions, path::Path};
+
+/// AIO block device created at runtime.
+#[derive(Debug)]
+pub struct AioBdev {
+    name: String,
+}
+
+impl AioBdev {
+    /// Create an AIO bdev on `filename` with blocks of `block_size` bytes.
+    ///
+    /// A regular file is created or extended to hold `num_blocks` blocks.
+    /// A file created here is removed again if the bdev cannot be registered,
+    /// an existing file stays extended.
+    /// The UUID of an AIO bdev is always generated by SPDK.
+    pub fn create(name: &str, filename: &str, num_blocks: u64, block_size: u32) -> Result<Self> {
+        Self::create_ext(name, filename, num_blocks, block_size, false)
+    }
+
+    /// Create a read-only AIO bdev on an existing `filename`.
+    pub fn create_readonly(name: &str, filename: &str, block_size: u32) -> Result<Self> {
+        Self::create_ext(name, filename, 0, block_size, true)
+    }
+
+    fn create_ext(
+        name: &str,
+        filename: &str,
+        num_blocks: u64,
+        block_size: u32,
+        readonly: bool,
+    ) -> Result<Self> {
+        let cname =
+            CString::new(name).map_err(|_| SpdkError::from(-(EINVAL as i32)).with_object(name))?;
+        let cfilename = CString::new(filename)
+            .map_err(|_| SpdkError::from(-(EINVAL as i32)).with_object(filename))?;
+        let mut created = false;
+        if !readonly {
+            let size = num_blocks
+                .checked_mul(block_size as u64)
+                .ok_or_else(|| SpdkError::from(-(EINVAL as i32)).with_op("create aio bdev"))?;
+            created = !Path::new(filename).exists();
+            let file = OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(filename)
+                .map_err(io_error)?;
+            let meta = file.metadata().map_err(io_error)?;
+            if meta.is_file() && meta.len() < size {
+                file.set_len(size).map_err(io_error)?;
+            }
+        }
+        let err =
+            unsafe { create_aio_bdev(cname.as_ptr(), cfilename.as_ptr(), block_size, readonly) };
+        if let Err(e) = SpdkError::from_retval(err) {
+            if created {
+                let _ = std::fs::remove_file(filename);
+            }
+            return Err(e);
+        }
+        Ok(AioBdev { name: name.into() })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the block device backed by the file.
+    pub fn bdev(&self) -> Option<BDev> {
+        BDev::get_by_name(&self.name)
+    }
+
+    /// Unregister the bdev. The backing file is kept.
+    pub async fn delete(self) -> Result<()> {
+        let cname = CString::new(self.name.as_str())
+            .map_err(|_| SpdkError::from(-(EINVAL as i32)).with_object(&self.name))?;
+        do_bdev_op(|arg| unsafe {
+            bdev_aio_delete(cname.as_ptr(), Some(bdev_op_callback), arg);
+        })
+        .await
+    }
+}
+
+fn io_error(e: std::io::Error) -> SpdkError {
+    SpdkError::from(-e.raw_os_error().unwrap_or(EIO as i32))
+}
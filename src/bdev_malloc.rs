@@ -0,0 +1,59 @@
+//! Malloc bdev, a block device backed by memory
+
+use crate::bdev::{bdev_op_callback, do_bdev_op, parse_uuid, BDev};
+use crate::{Result, SpdkError};
+use spdk_sys::*;
+use std::{ffi::CString, mem::MaybeUninit};
+
+/// Malloc block device created at runtime.
+///
+/// Dropping the handle keeps the bdev and its memory, only `delete` frees them.
+#[derive(Debug)]
+pub struct MallocBdev {
+    name: String,
+}
+
+impl MallocBdev {
+    /// Create a malloc bdev of `num_blocks` blocks of `block_size` bytes.
+    ///
+    /// A random UUID is generated if `uuid` is `None`.
+    pub fn create(
+        name: &str,
+        num_blocks: u64,
+        block_size: u32,
+        uuid: Option<&str>,
+    ) -> Result<Self> {
+        let cname =
+            CString::new(name).map_err(|_| SpdkError::from(-(EINVAL as i32)).with_object(name))?;
+        let mut opts: malloc_bdev_opts = unsafe { MaybeUninit::zeroed().assume_init() };
+        opts.name = cname.as_ptr() as _;
+        opts.num_blocks = num_blocks;
+        opts.block_size = block_size;
+        if let Some(uuid) = uuid {
+            opts.uuid = parse_uuid(uuid)?;
+        }
+        let mut bdev = std::ptr::null_mut();
+        let err = unsafe { create_malloc_disk(&mut bdev, &opts) };
+        SpdkError::from_retval(err)?;
+        Ok(MallocBdev { name: name.into() })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the block device, `None` once it was deleted.
+    pub fn bdev(&self) -> Option<BDev> {
+        BDev::get_by_name(&self.name)
+    }
+
+    /// Unregister the bdev and free its memory.
+    pub async fn delete(self) -> Result<()> {
+        let cname = CString::new(self.name.as_str())
+            .map_err(|_| SpdkError::from(-(EINVAL as i32)).with_object(&self.name))?;
+        do_bdev_op(|arg| unsafe {
+            delete_malloc_disk(cname.as_ptr(), Some(bdev_op_callback), arg);
+        })
+        .await
+    }
+}
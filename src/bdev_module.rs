@@ -0,0 +1,393 @@
+//! Block devices implemented in Rust
+//!
+//! A [`BdevModule`] is registered once before the SPDK app starts. Each bdev of the
+//! module is a [`BdevBackend`] registered with [`BdevModuleRef::register_bdev`]. It
+//! then appears as a normal SPDK bdev, e.g. to blobstore or other bdev modules.
+
+use crate::bdev::{bdev_op_callback, parse_uuid, BDev, BdevIoType};
+use crate::{complete::LocalComplete, Result, SpdkError};
+use log::*;
+use spdk_sys::*;
+use std::{
+    any::TypeId,
+    ffi::{c_void, CString},
+    mem::MaybeUninit,
+    os::raw::c_int,
+    slice::from_raw_parts_mut,
+    sync::Mutex,
+};
+
+/// A bdev module, grouping the bdevs implemented by one kind of backend.
+pub trait BdevModule: 'static {
+    /// Name of the module, unique among all bdev modules.
+    const NAME: &'static str;
+
+    /// Called when the bdev subsystem is initialized.
+    fn init() -> Result<()> {
+        Ok(())
+    }
+
+    /// Called when the bdev subsystem is finished, after all bdevs are unregistered.
+    fn fini() {}
+}
+
+/// Backend of a bdev implemented in Rust.
+///
+/// `submit_request` is called on the thread of the channel the I/O was submitted on,
+/// so the backend is shared across threads.
+pub trait BdevBackend: Send + Sync + 'static {
+    /// Per-channel context, created on each thread that opens an I/O channel.
+    type Channel: 'static;
+
+    /// Whether the backend supports the given I/O type.
+    fn io_type_supported(&self, io_type: BdevIoType) -> bool;
+
+    /// Create the context for a new I/O channel on the current thread.
+    fn create_channel(&self) -> Result<Self::Channel>;
+
+    /// Handle an I/O request. It must be completed exactly once with `BdevIoRequest::complete`.
+    fn submit_request(&self, channel: &mut Self::Channel, io: BdevIoRequest);
+}
+
+/// Registered bdev modules by name, with the module type and address.
+static MODULES: Mutex<Vec<(&'static str, TypeId, usize)>> = Mutex::new(Vec::new());
+
+/// Handle to a registered bdev module.
+#[derive(Debug, Clone, Copy)]
+pub struct BdevModuleRef {
+    ptr: *mut spdk_bdev_module,
+}
+
+unsafe impl Send for BdevModuleRef {}
+unsafe impl Sync for BdevModuleRef {}
+
+impl BdevModuleRef {
+    /// Register the bdev module `M`, before the SPDK app is started.
+    ///
+    /// Registering `M` again returns the same module, another module
+    /// with the same name fails with `EEXIST`.
+    pub fn register<M: BdevModule>() -> Result<Self> {
+        extern "C" fn module_init<M: BdevModule>() -> c_int {
+            match M::init() {
+                Ok(()) => 0,
+                Err(e) => e.errno(),
+            }
+        }
+        extern "C" fn module_fini<M: BdevModule>() {
+            M::fini();
+        }
+        let mut modules = MODULES.lock().unwrap();
+        if let Some(&(_, id, ptr)) = modules.iter().find(|(name, ..)| *name == M::NAME) {
+            if id != TypeId::of::<M>() {
+                return Err(SpdkError::from(-(EEXIST as i32))
+                    .with_op("register bdev module")
+                    .with_object(M::NAME));
+            }
+            return Ok(BdevModuleRef { ptr: ptr as _ });
+        }
+        let name = CString::new(M::NAME)
+            .map_err(|_| SpdkError::from(-(EINVAL as i32)).with_object(M::NAME))?;
+        let mut module: spdk_bdev_module = unsafe { MaybeUninit::zeroed().assume_init() };
+        module.name = name.into_raw();
+        module.module_init = Some(module_init::<M>);
+        module.module_fini = Some(module_fini::<M>);
+        // SPDK keeps the module in its list until exit
+        let ptr = Box::into_raw(Box::new(module));
+        unsafe { spdk_bdev_module_list_add(ptr) };
+        modules.push((M::NAME, TypeId::of::<M>(), ptr as usize));
+        Ok(BdevModuleRef { ptr })
+    }
+
+    /// Register a bdev of this module backed by `backend`.
+    pub fn register_bdev<B: BdevBackend>(
+        &self,
+        opts: BdevRegisterOpts,
+        backend: B,
+    ) -> Result<BDev> {
+        let name = CString::new(opts.name.as_str())
+            .map_err(|_| SpdkError::from(-(EINVAL as i32)).with_object(&opts.name))?;
+        let product_name = CString::new(opts.product_name.as_str())
+            .map_err(|_| SpdkError::from(-(EINVAL as i32)).with_object(&opts.product_name))?;
+        let ctx = Box::into_raw(Box::new(BdevCtx {
+            bdev: unsafe { MaybeUninit::zeroed().assume_init() },
+            fn_table: fn_table::<B>(),
+            backend,
+            name,
+            product_name,
+        }));
+        unsafe {
+            let bdev = &mut (*ctx).bdev;
+            bdev.name = (*ctx).name.as_ptr() as _;
+            bdev.product_name = (*ctx).product_name.as_ptr() as _;
+            bdev.blocklen = opts.block_size;
+            bdev.blockcnt = opts.num_blocks;
+            bdev.write_cache = opts.write_cache as _;
+            if let Some(uuid) = &opts.uuid {
+                match parse_uuid(uuid) {
+                    Ok(uuid) => bdev.uuid = uuid,
+                    Err(e) => {
+                        drop(Box::from_raw(ctx));
+                        return Err(e);
+                    }
+                }
+            }
+            bdev.ctxt = ctx as _;
+            bdev.fn_table = &(*ctx).fn_table;
+            bdev.module = self.ptr;
+
+            spdk_io_device_register(
+                ctx as _,
+                Some(create_channel::<B>),
+                Some(destroy_channel::<B>),
+                std::mem::size_of::<*mut B::Channel>() as u32,
+                (*ctx).name.as_ptr(),
+            );
+            let err = spdk_bdev_register(bdev);
+            if err != 0 {
+                spdk_io_device_unregister(ctx as _, Some(free_ctx::<B>));
+                return Err(SpdkError::from(err));
+            }
+            Ok(BDev {
+                ptr: bdev as *mut _,
+            })
+        }
+    }
+
+    /// Unregister a bdev of this module by name.
+    ///
+    /// The backend is dropped once all its channels are released.
+    pub async fn unregister_bdev(&self, name: &str) -> Result<()> {
+        let cname =
+            CString::new(name).map_err(|_| SpdkError::from(-(EINVAL as i32)).with_object(name))?;
+        let complete = LocalComplete::<Result<()>>::new();
+        futures_lite::pin!(complete);
+        let err = unsafe {
+            spdk_bdev_unregister_by_name(
+                cname.as_ptr(),
+                self.ptr,
+                Some(bdev_op_callback),
+                complete.as_arg(),
+            )
+        };
+        SpdkError::from_retval(err)?;
+        complete.await
+    }
+}
+
+/// Options for registering a bdev implemented in Rust.
+#[derive(Debug, Clone)]
+pub struct BdevRegisterOpts {
+    name: String,
+    product_name: String,
+    block_size: u32,
+    num_blocks: u64,
+    uuid: Option<String>,
+    write_cache: bool,
+}
+
+impl BdevRegisterOpts {
+    /// A bdev of `num_blocks` blocks of `block_size` bytes.
+    pub fn new(name: &str, block_size: u32, num_blocks: u64) -> Self {
+        BdevRegisterOpts {
+            name: name.into(),
+            product_name: "Rust bdev".into(),
+            block_size,
+            num_blocks,
+            uuid: None,
+            write_cache: false,
+        }
+    }
+
+    pub fn product_name(mut self, product_name: &str) -> Self {
+        self.product_name = product_name.into();
+        self
+    }
+
+    /// UUID of the bdev. A random one is generated if not set.
+    pub fn uuid(mut self, uuid: &str) -> Self {
+        self.uuid = Some(uuid.into());
+        self
+    }
+
+    /// Whether the backend has a volatile write cache and needs flushes.
+    pub fn write_cache(mut self, write_cache: bool) -> Self {
+        self.write_cache = write_cache;
+        self
+    }
+}
+
+/// I/O request submitted to a [`BdevBackend`].
+///
+/// Dropping a request without completing it fails the I/O.
+#[derive(Debug)]
+pub struct BdevIoRequest {
+    ptr: *mut spdk_bdev_io,
+}
+
+impl BdevIoRequest {
+    /// Get the I/O type, `None` for types unknown to this crate.
+    pub fn io_type(&self) -> Option<BdevIoType> {
+        BdevIoType::from_raw(unsafe { (*self.ptr).type_ } as spdk_bdev_io_type)
+    }
+
+    /// Get the first block of a read, write, unmap, write zeroes or flush.
+    pub fn offset_blocks(&self) -> u64 {
+        unsafe { (*self.ptr).u.bdev.offset_blocks }
+    }
+
+    /// Get the number of blocks of a read, write, unmap, write zeroes or flush.
+    pub fn num_blocks(&self) -> u64 {
+        unsafe { (*self.ptr).u.bdev.num_blocks }
+    }
+
+    /// Get the block size of the bdev in bytes.
+    pub fn block_size(&self) -> u32 {
+        unsafe { (*(*self.ptr).bdev).blocklen }
+    }
+
+    /// Get the data buffers of a read or write.
+    pub fn buffers(&mut self) -> Vec<&mut [u8]> {
+        unsafe {
+            let bdev = &(*self.ptr).u.bdev;
+            if bdev.iovs.is_null() {
+                return vec![];
+            }
+            std::slice::from_raw_parts(bdev.iovs, bdev.iovcnt as usize)
+                .iter()
+                .map(|iov| from_raw_parts_mut(iov.iov_base as *mut u8, iov.iov_len as usize))
+                .collect()
+        }
+    }
+
+    /// Complete the request.
+    pub fn complete(mut self, result: Result<()>) {
+        let status = match result {
+            Ok(()) => spdk_bdev_io_status_SPDK_BDEV_IO_STATUS_SUCCESS,
+            Err(e) if e.errno() == -(ENOMEM as i32) => {
+                // SPDK resubmits the I/O later
+                spdk_bdev_io_status_SPDK_BDEV_IO_STATUS_NOMEM
+            }
+            Err(_) => spdk_bdev_io_status_SPDK_BDEV_IO_STATUS_FAILED,
+        };
+        unsafe { spdk_bdev_io_complete(self.ptr, status) };
+        self.ptr = std::ptr::null_mut();
+    }
+}
+
+impl Drop for BdevIoRequest {
+    fn drop(&mut self) {
+        if !self.ptr.is_null() {
+            warn!("bdev I/O request dropped without completion");
+            unsafe {
+                spdk_bdev_io_complete(self.ptr, spdk_bdev_io_status_SPDK_BDEV_IO_STATUS_FAILED)
+            };
+        }
+    }
+}
+
+/// Context of a registered bdev, also used as its io_device.
+struct BdevCtx<B: BdevBackend> {
+    bdev: spdk_bdev,
+    fn_table: spdk_bdev_fn_table,
+    backend: B,
+    name: CString,
+    product_name: CString,
+}
+
+fn fn_table<B: BdevBackend>() -> spdk_bdev_fn_table {
+    let mut table: spdk_bdev_fn_table = unsafe { MaybeUninit::zeroed().assume_init() };
+    table.destruct = Some(destruct::<B>);
+    table.submit_request = Some(submit_request::<B>);
+    table.io_type_supported = Some(io_type_supported::<B>);
+    table.get_io_channel = Some(get_io_channel);
+    table
+}
+
+extern "C" fn destruct<B: BdevBackend>(ctx: *mut c_void) -> c_int {
+    // wait for all channels to be released before dropping the backend
+    unsafe { spdk_io_device_unregister(ctx, Some(destruct_done::<B>)) };
+    // destruct completes asynchronously
+    1
+}
+
+extern "C" fn destruct_done<B: BdevBackend>(ctx: *mut c_void) {
+    let ctx = ctx as *mut BdevCtx<B>;
+    unsafe {
+        spdk_bdev_destruct_done(&mut (*ctx).bdev, 0);
+        drop(Box::from_raw(ctx));
+    }
+}
+
+extern "C" fn free_ctx<B: BdevBackend>(ctx: *mut c_void) {
+    unsafe { drop(Box::from_raw(ctx as *mut BdevCtx<B>)) };
+}
+
+extern "C" fn submit_request<B: BdevBackend>(ch: *mut spdk_io_channel, bdev_io: *mut spdk_bdev_io) {
+    unsafe {
+        let bdev = &*(*bdev_io).bdev;
+        let is_read =
+            (*bdev_io).type_ as spdk_bdev_io_type == spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_READ;
+        let iovs = (*bdev_io).u.bdev.iovs;
+        if is_read && (iovs.is_null() || (*iovs).iov_base.is_null()) {
+            // the caller did not provide a buffer
+            let len = (*bdev_io).u.bdev.num_blocks * bdev.blocklen as u64;
+            spdk_bdev_io_get_buf(bdev_io, Some(get_buf_callback::<B>), len as _);
+            return;
+        }
+    }
+    dispatch::<B>(ch, bdev_io);
+}
+
+extern "C" fn get_buf_callback<B: BdevBackend>(
+    ch: *mut spdk_io_channel,
+    bdev_io: *mut spdk_bdev_io,
+    success: bool,
+) {
+    if !success {
+        unsafe { spdk_bdev_io_complete(bdev_io, spdk_bdev_io_status_SPDK_BDEV_IO_STATUS_FAILED) };
+        return;
+    }
+    dispatch::<B>(ch, bdev_io);
+}
+
+fn dispatch<B: BdevBackend>(ch: *mut spdk_io_channel, bdev_io: *mut spdk_bdev_io) {
+    unsafe {
+        let ctx = &*((*(*bdev_io).bdev).ctxt as *const BdevCtx<B>);
+        let channel = &mut **(spdk_io_channel_get_ctx(ch) as *mut *mut B::Channel);
+        ctx.backend
+            .submit_request(channel, BdevIoRequest { ptr: bdev_io });
+    }
+}
+
+extern "C" fn io_type_supported<B: BdevBackend>(
+    ctx: *mut c_void,
+    io_type: spdk_bdev_io_type,
+) -> bool {
+    let ctx = unsafe { &*(ctx as *const BdevCtx<B>) };
+    BdevIoType::from_raw(io_type).is_some_and(|t| ctx.backend.io_type_supported(t))
+}
+
+extern "C" fn get_io_channel(ctx: *mut c_void) -> *mut spdk_io_channel {
+    unsafe { spdk_get_io_channel(ctx) }
+}
+
+extern "C" fn create_channel<B: BdevBackend>(
+    io_device: *mut c_void,
+    ctx_buf: *mut c_void,
+) -> c_int {
+    let ctx = unsafe { &*(io_device as *const BdevCtx<B>) };
+    match ctx.backend.create_channel() {
+        Ok(channel) => {
+            unsafe { *(ctx_buf as *mut *mut B::Channel) = Box::into_raw(Box::new(channel)) };
+            0
+        }
+        Err(e) => {
+            error!("failed to create channel for bdev {:?}: {}", ctx.name, e);
+            e.errno()
+        }
+    }
+}
+
+extern "C" fn destroy_channel<B: BdevBackend>(_io_device: *mut c_void, ctx_buf: *mut c_void) {
+    unsafe { drop(Box::from_raw(*(ctx_buf as *mut *mut B::Channel))) };
+}
@@ -0,0 +1,58 @@
+//! Null bdev, a block device that discards writes and returns zeros on reads
+
+use crate::bdev::{bdev_op_callback, do_bdev_op, parse_uuid, BDev};
+use crate::{Result, SpdkError};
+use spdk_sys::*;
+use std::{ffi::CString, mem::MaybeUninit};
+
+/// Null block device created at runtime.
+#[derive(Debug)]
+pub struct NullBdev {
+    name: String,
+}
+
+impl NullBdev {
+    /// Create a null bdev of `num_blocks` blocks of `block_size` bytes.
+    ///
+    /// A random UUID is generated if `uuid` is `None`.
+    pub fn create(
+        name: &str,
+        num_blocks: u64,
+        block_size: u32,
+        uuid: Option<&str>,
+    ) -> Result<Self> {
+        let cname =
+            CString::new(name).map_err(|_| SpdkError::from(-(EINVAL as i32)).with_object(name))?;
+        let uuid = uuid.map(parse_uuid).transpose()?;
+        let mut opts: spdk_null_bdev_opts = unsafe { MaybeUninit::zeroed().assume_init() };
+        opts.name = cname.as_ptr();
+        opts.num_blocks = num_blocks;
+        opts.block_size = block_size;
+        if let Some(uuid) = &uuid {
+            opts.uuid = uuid;
+        }
+        let mut bdev = std::ptr::null_mut();
+        let err = unsafe { bdev_null_create(&mut bdev, &opts) };
+        SpdkError::from_retval(err)?;
+        Ok(NullBdev { name: name.into() })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Look up the bdev by name.
+    pub fn bdev(&self) -> Option<BDev> {
+        BDev::get_by_name(&self.name)
+    }
+
+    /// Unregister the bdev.
+    pub async fn delete(self) -> Result<()> {
+        let cname = CString::new(self.name.as_str())
+            .map_err(|_| SpdkError::from(-(EINVAL as i32)).with_object(&self.name))?;
+        do_bdev_op(|arg| unsafe {
+            bdev_null_delete(cname.as_ptr(), Some(bdev_op_callback), arg);
+        })
+        .await
+    }
+}
@@ -1,14 +1,28 @@
 //! Blob Storage System
 
-use crate::{blob_bdev::BlobStoreBDev, complete::LocalComplete, error::*};
+use crate::{
+    bdev::IoVec, blob_bdev::BlobStoreBDev, complete::LocalComplete, env::DmaBuf, error::*, event,
+};
+use futures_lite::Stream;
 use log::*;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use spdk_sys::*;
-use std::ffi::c_void;
+use std::ffi::{c_void, CStr, CString};
 use std::fmt;
-use std::os::raw::c_int;
+use std::marker::PhantomData;
+use std::mem::{size_of, MaybeUninit};
+use std::ops::Range;
+use std::os::raw::{c_char, c_int};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use tokio::sync::Notify;
+use std::task::{Context, Poll, Waker};
+use std::{
+    cell::{Cell, RefCell},
+    future::Future,
+    rc::Rc,
+};
+use tokio::sync::{oneshot, Notify};
 
 #[derive(Debug)]
 pub struct Blobstore {
@@ -52,32 +66,86 @@ impl Blobstore {
         unsafe { spdk_bs_total_data_cluster_count(self.ptr) }
     }
 
+    /// Get the blobstore type set at initialization.
+    pub fn bstype(&self) -> String {
+        let bstype = unsafe { spdk_bs_get_bstype(self.ptr) };
+        let bytes = bstype
+            .bstype
+            .iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| c as u8)
+            .collect::<Vec<_>>();
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    /// Set the super blob, which applications use to find their root object.
+    pub async fn set_super_blob(&self, blob_id: BlobId) -> Result<()> {
+        do_async(|arg| unsafe {
+            spdk_bs_set_super(self.ptr, blob_id.id, Some(callback), arg);
+        })
+        .await
+        .with_op("set super blob")
+        .with_object(blob_id)
+    }
+
+    /// Get the super blob, or `None` if it is not set.
+    pub async fn get_super_blob(&self) -> Result<Option<BlobId>> {
+        let result = do_async(|arg| unsafe {
+            spdk_bs_get_super(self.ptr, Some(callback_with), arg);
+        })
+        .await;
+        match result {
+            Ok(id) => Ok(Some(BlobId { id })),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.with_op("get super blob")),
+        }
+    }
+
+    /// Get a handle to use this blobstore from other threads.
+    ///
+    /// Must be called on the SPDK thread that owns the blobstore, requests
+    /// through the handle are forwarded to it.
+    pub fn handle(&self) -> Result<BlobstoreHandle> {
+        let thread = unsafe { spdk_get_thread() };
+        if thread.is_null() {
+            return Err(SpdkError::from(-(EINVAL as i32)).with_op("create blobstore handle"));
+        }
+        Ok(BlobstoreHandle {
+            state: Arc::new(HandleState {
+                bs: self.ptr,
+                thread,
+                io_unit_size: self.io_unit_size(),
+                unloaded: AtomicBool::new(false),
+            }),
+        })
+    }
+
     /// Allocate an I/O channel for the given blobstore.
     pub fn alloc_io_channel(&self) -> Result<IoChannel> {
         let ptr = unsafe { spdk_bs_alloc_io_channel(self.ptr) };
         if ptr.is_null() {
-            // FIXME: proper error
-            return Err(SpdkError::from(-1));
+            return Err(SpdkError::from(-(ENOMEM as i32)).with_op("allocate io channel"));
         }
         Ok(IoChannel { ptr })
     }
 
     /// Initialize a blobstore on the given device.
     pub async fn init(bs_dev: &mut BlobStoreBDev) -> Result<Blobstore> {
-        let ptr = do_async(|arg| unsafe {
-            spdk_bs_init(bs_dev.ptr, std::ptr::null_mut(), Some(callback_with), arg);
-        })
-        .await?;
-        Ok(Blobstore { ptr })
+        BlobstoreOpts::new().init(bs_dev).await
     }
 
     pub fn init_sync(bs_dev: &mut BlobStoreBDev, cb_arg: *mut c_void) -> Result<()> {
+        // the callback records the blobstore on the device, for auto-grow
+        let arg = Box::new(InitSyncArg {
+            slot: cb_arg,
+            blobstore: bs_dev.blobstore_slot(),
+        });
         unsafe {
             spdk_bs_init(
                 bs_dev.ptr,
                 std::ptr::null_mut(),
                 Some(init_callback),
-                cb_arg,
+                Box::into_raw(arg) as _,
             );
         };
         Ok(())
@@ -85,25 +153,37 @@ impl Blobstore {
 
     /// Load a blobstore on the given device
     pub async fn load(bs_dev: &mut BlobStoreBDev) -> Result<Blobstore> {
-        let ptr = do_async(|arg| unsafe {
-            spdk_bs_load(bs_dev.ptr, std::ptr::null_mut(), Some(callback_with), arg);
-        })
-        .await?;
-        Ok(Blobstore { ptr })
+        BlobstoreOpts::new().load(bs_dev).await
     }
 
     pub fn load_sync(bs_dev: &mut BlobStoreBDev, cb_arg: *mut c_void) -> Result<()> {
+        // the callback records the blobstore on the device, for auto-grow
+        let arg = Box::new(InitSyncArg {
+            slot: cb_arg,
+            blobstore: bs_dev.blobstore_slot(),
+        });
         unsafe {
             spdk_bs_load(
                 bs_dev.ptr,
                 std::ptr::null_mut(),
                 Some(init_callback),
-                cb_arg,
+                Box::into_raw(arg) as _,
             );
         };
         Ok(())
     }
 
+    /// Grow the loaded blobstore to take in the clusters added by resizing its device.
+    ///
+    /// Does nothing if the device has not grown.
+    pub async fn grow(&self) -> Result<()> {
+        do_async(|arg| unsafe {
+            spdk_bs_grow_live(self.ptr, Some(callback), arg);
+        })
+        .await
+        .with_op("grow blobstore")
+    }
+
     /// Unload the blobstore.
     ///
     /// It will flush all volatile data to disk.
@@ -112,10 +192,12 @@ impl Blobstore {
         do_async(|arg| unsafe {
             spdk_bs_unload(self.ptr, Some(callback), arg);
         })
-        .await?;
+        .await
+        .with_op("unload blobstore")?;
         Ok(())
     }
 
+    #[deprecated(note = "use `BlobstoreHandle` instead")]
     pub fn unload_sync(&self, cb_arg: *mut c_void) -> Result<()> {
         if self.ptr.is_null() {
             error!("blobstore ptr is null");
@@ -131,13 +213,44 @@ impl Blobstore {
         let id = do_async(|arg| unsafe {
             spdk_bs_create_blob(self.ptr, Some(callback_with), arg);
         })
-        .await?;
+        .await
+        .with_op("create blob")?;
+        Ok(BlobId { id })
+    }
+
+    /// Create a new blob with the given options.
+    ///
+    /// The initial size and xattrs are persisted along with the new blob.
+    pub async fn create_blob_ext(&self, opts: &BlobOpts) -> Result<BlobId> {
+        let mut names = opts
+            .xattrs
+            .iter()
+            .map(|(name, _)| name.as_ptr() as *mut c_char)
+            .collect::<Vec<_>>();
+        let mut raw = MaybeUninit::uninit();
+        let mut raw = unsafe {
+            spdk_blob_opts_init(raw.as_mut_ptr(), size_of::<spdk_blob_opts>() as _);
+            raw.assume_init()
+        };
+        raw.num_clusters = opts.num_clusters;
+        raw.thin_provision = opts.thin_provision;
+        raw.clear_method = opts.clear_method.as_raw();
+        raw.xattrs.count = names.len() as _;
+        raw.xattrs.names = names.as_mut_ptr();
+        raw.xattrs.ctx = &opts.xattrs as *const XattrList as _;
+        raw.xattrs.get_value = Some(get_xattr_value);
+        let id = do_async(|arg| unsafe {
+            spdk_bs_create_blob_ext(self.ptr, &raw, Some(callback_with), arg);
+        })
+        .await
+        .with_op("create blob")?;
         Ok(BlobId { id })
     }
 
     /// Create blob, sync API
     ///
     /// cb_arg: Arc<Mutex< BlobId >>
+    #[deprecated(note = "use `BlobstoreHandle` instead")]
     pub fn create_blob_sync(
         &self,
         // cb_fn: extern "C" fn(*mut c_void, spdk_blob_id, i32),
@@ -150,26 +263,41 @@ impl Blobstore {
     }
 
     /// Open a blob from the given blobstore.
-    pub async fn open_blob(&self, blob_id: BlobId) -> Result<Blob> {
+    pub async fn open_blob(&self, blob_id: BlobId) -> Result<Blob<'_>> {
         let ptr = do_async(|arg| unsafe {
             spdk_bs_open_blob(self.ptr, blob_id.id, Some(callback_with), arg);
         })
-        .await?;
-        let io_unit_size = self.io_unit_size();
-        Ok(Blob { ptr, io_unit_size })
+        .await
+        .with_op("open blob")
+        .with_object(blob_id)?;
+        Ok(Blob::from_raw(ptr, self.ptr, self.io_unit_size()))
     }
 
     /// Open blob, sync API
     ///
-    /// cb_arg: Arc<Mutex<Blob>>
+    /// cb_arg: Box<(Arc<Mutex<Option<Blob<'static>>>>, Arc<Notify>)>
+    ///
+    /// The blobstore must outlive the opened blob.
+    #[deprecated(note = "use `BlobstoreHandle` instead")]
     pub fn open_blob_sync(
         &self,
         blob_id: &BlobId,
         // cb_fn: extern "C" fn(*mut c_void, *mut spdk_blob, c_int),
         cb_arg: *mut c_void,
     ) -> Result<()> {
+        // the callback needs the blobstore to fill in the slot of `cb_arg`
+        let arg = Box::new(OpenSyncArg {
+            slot: cb_arg,
+            bs: self.ptr,
+            io_unit_size: self.io_unit_size(),
+        });
         unsafe {
-            spdk_bs_open_blob(self.ptr, blob_id.id, Some(open_callback), cb_arg);
+            spdk_bs_open_blob(
+                self.ptr,
+                blob_id.id,
+                Some(open_callback),
+                Box::into_raw(arg) as _,
+            );
         }
         Ok(())
     }
@@ -179,24 +307,294 @@ impl Blobstore {
         do_async(|arg| unsafe {
             spdk_bs_delete_blob(self.ptr, blob_id.id, Some(callback), arg);
         })
-        .await?;
+        .await
+        .with_op("delete blob")
+        .with_object(blob_id)?;
         Ok(())
     }
 
     /// Delete blob, sync API
+    #[deprecated(note = "use `BlobstoreHandle` instead")]
     pub fn delete_blob_sync(&self, blob_id: &BlobId, cb_arg: *mut c_void) -> Result<()> {
         unsafe {
             spdk_bs_delete_blob(self.ptr, blob_id.id, Some(delete_callback), cb_arg);
         }
         Ok(())
     }
+
+    /// Create a read-only snapshot of a blob.
+    ///
+    /// The blob becomes a thin provisioned clone of the new snapshot.
+    pub async fn create_snapshot(&self, blob_id: BlobId) -> Result<BlobId> {
+        let id = do_async(|arg| unsafe {
+            spdk_bs_create_snapshot(
+                self.ptr,
+                blob_id.id,
+                std::ptr::null(),
+                Some(callback_with),
+                arg,
+            );
+        })
+        .await
+        .with_op("create snapshot")
+        .with_object(blob_id)?;
+        Ok(BlobId { id })
+    }
+
+    /// Create a thin provisioned clone of a snapshot.
+    pub async fn create_clone(&self, snapshot_id: BlobId) -> Result<BlobId> {
+        let id = do_async(|arg| unsafe {
+            spdk_bs_create_clone(
+                self.ptr,
+                snapshot_id.id,
+                std::ptr::null(),
+                Some(callback_with),
+                arg,
+            );
+        })
+        .await
+        .with_op("create clone")
+        .with_object(snapshot_id)?;
+        Ok(BlobId { id })
+    }
+
+    /// Allocate all clusters of a thin provisioned blob and copy the data
+    /// of its parents, removing the dependency on them.
+    pub async fn inflate_blob(&self, io_channel: &IoChannel, blob_id: BlobId) -> Result<()> {
+        do_async(|arg| unsafe {
+            spdk_bs_inflate_blob(self.ptr, io_channel.ptr, blob_id.id, Some(callback), arg);
+        })
+        .await
+        .with_op("inflate blob")
+        .with_object(blob_id)
+    }
+
+    /// Remove the dependency of a clone on its direct parent snapshot,
+    /// copying only the clusters it provides. The clone stays thin provisioned.
+    pub async fn decouple_parent(&self, io_channel: &IoChannel, blob_id: BlobId) -> Result<()> {
+        do_async(|arg| unsafe {
+            spdk_bs_blob_decouple_parent(self.ptr, io_channel.ptr, blob_id.id, Some(callback), arg);
+        })
+        .await
+        .with_op("decouple parent")
+        .with_object(blob_id)
+    }
+
+    /// Get the snapshot a blob was cloned from, if any.
+    pub fn parent_snapshot(&self, blob_id: BlobId) -> Option<BlobId> {
+        let id = unsafe { spdk_blob_get_parent_snapshot(self.ptr, blob_id.id) };
+        if id == BLOBID_INVALID {
+            return None;
+        }
+        Some(BlobId { id })
+    }
+
+    /// Get the clones of a snapshot.
+    pub fn clones(&self, blob_id: BlobId) -> Result<Vec<BlobId>> {
+        let mut ids: Vec<spdk_blob_id> = Vec::new();
+        loop {
+            let mut count = ids.len() as _;
+            let err =
+                unsafe { spdk_blob_get_clones(self.ptr, blob_id.id, ids.as_mut_ptr(), &mut count) };
+            if err == -(ENOMEM as i32) && count as usize > ids.len() {
+                ids.resize(count as usize, BLOBID_INVALID);
+                continue;
+            }
+            SpdkError::from_retval(err)
+                .with_op("get clones")
+                .with_object(blob_id)?;
+            ids.truncate(count as usize);
+            return Ok(ids.into_iter().map(|id| BlobId { id }).collect());
+        }
+    }
+
+    /// Iterate over all blobs in the blobstore.
+    ///
+    /// Each item is an independent handle, the stream releases its own
+    /// reference to a blob when it moves on or is dropped.
+    pub fn iter_blobs(&self) -> BlobStream<'_> {
+        BlobStream {
+            bs: self,
+            state: Rc::new(RefCell::new(BlobIterState::default())),
+            current: std::ptr::null_mut(),
+            started: false,
+            pending: None,
+            done: false,
+        }
+    }
+}
+
+/// Stream over the blobs of a blobstore, see `Blobstore::iter_blobs`.
+#[derive(Debug)]
+pub struct BlobStream<'a> {
+    bs: &'a Blobstore,
+    state: Rc<RefCell<BlobIterState>>,
+    /// Reference of the iterator to the current blob, released by `spdk_bs_iter_next`.
+    current: *mut spdk_blob,
+    started: bool,
+    pending: Option<IterStep>,
+    done: bool,
 }
 
+/// Operation in flight of a `BlobStream`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IterStep {
+    /// Moving to the next blob.
+    Next,
+    /// Opening the current blob again for the yielded handle.
+    Reopen,
+}
+
+/// Completion of `spdk_bs_iter_first`/`spdk_bs_iter_next`.
+#[derive(Debug, Default)]
+struct BlobIterState {
+    result: Option<Result<*mut spdk_blob>>,
+    waker: Option<Waker>,
+    /// The stream is gone, close the blob on completion.
+    dropped: bool,
+}
+
+impl<'a> Stream for BlobStream<'a> {
+    type Item = Result<Blob<'a>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Blob<'a>>>> {
+        loop {
+            if self.done {
+                return Poll::Ready(None);
+            }
+            if self.pending.is_none() {
+                self.start(IterStep::Next);
+            }
+            let mut state = self.state.borrow_mut();
+            let result = match state.result.take() {
+                Some(result) => result,
+                None => {
+                    state.waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            };
+            drop(state);
+            let step = self.pending.take();
+            match (step, result) {
+                (Some(IterStep::Next), Ok(ptr)) => {
+                    self.current = ptr;
+                    self.start(IterStep::Reopen);
+                }
+                (_, Ok(ptr)) => {
+                    let blob = Blob::from_raw(ptr, self.bs.ptr, self.bs.io_unit_size());
+                    return Poll::Ready(Some(Ok(blob)));
+                }
+                (_, Err(e)) => {
+                    self.done = true;
+                    if e.kind() == ErrorKind::NotFound {
+                        return Poll::Ready(None);
+                    }
+                    return Poll::Ready(Some(Err(e.with_op("iterate blobs"))));
+                }
+            }
+        }
+    }
+}
+
+impl BlobStream<'_> {
+    fn start(&mut self, step: IterStep) {
+        let arg = Rc::into_raw(self.state.clone()) as *mut c_void;
+        let bs = self.bs.ptr;
+        self.pending = Some(step);
+        match step {
+            IterStep::Next if self.started => {
+                // SPDK closes the current blob before opening the next one
+                let current = std::mem::replace(&mut self.current, std::ptr::null_mut());
+                unsafe { spdk_bs_iter_next(bs, current, Some(iter_callback), arg) };
+            }
+            IterStep::Next => {
+                self.started = true;
+                unsafe { spdk_bs_iter_first(bs, Some(iter_callback), arg) };
+            }
+            IterStep::Reopen => unsafe {
+                // the blob is open, so this only takes another reference to it
+                let id = spdk_blob_get_id(self.current);
+                spdk_bs_open_blob(bs, id, Some(iter_callback), arg);
+            },
+        }
+    }
+}
+
+impl Drop for BlobStream<'_> {
+    fn drop(&mut self) {
+        let mut state = self.state.borrow_mut();
+        if self.pending.is_some() {
+            state.dropped = true;
+        }
+        // a blob opened by an operation that completed since the last poll
+        if let Some(Ok(blob)) = state.result.take() {
+            unsafe { spdk_blob_close(blob, Some(iter_close_callback), std::ptr::null_mut()) };
+        }
+        drop(state);
+        if !self.current.is_null() {
+            unsafe {
+                spdk_blob_close(
+                    self.current,
+                    Some(iter_close_callback),
+                    std::ptr::null_mut(),
+                )
+            };
+        }
+    }
+}
+
+extern "C" fn iter_callback(arg: *mut c_void, blob: *mut spdk_blob, bserrno: c_int) {
+    let state = unsafe { Rc::from_raw(arg as *const RefCell<BlobIterState>) };
+    let mut state = state.borrow_mut();
+    if state.dropped {
+        if bserrno == 0 {
+            unsafe { spdk_blob_close(blob, Some(iter_close_callback), std::ptr::null_mut()) };
+        }
+        return;
+    }
+    state.result = Some(if bserrno != 0 {
+        Err(SpdkError::from(bserrno))
+    } else {
+        Ok(blob)
+    });
+    if let Some(waker) = state.waker.take() {
+        waker.wake();
+    }
+}
+
+/// Grow a blobstore without waiting for the result, e.g. from an event handler.
+pub(crate) fn grow_detached(bs: *mut spdk_blob_store) {
+    unsafe { spdk_bs_grow_live(bs, Some(grow_callback), std::ptr::null_mut()) };
+}
+
+extern "C" fn grow_callback(_arg: *mut c_void, bserrno: c_int) {
+    if bserrno != 0 {
+        error!("grow blobstore error: {}", bserrno);
+    } else {
+        info!("blobstore grown");
+    }
+}
+
+extern "C" fn iter_close_callback(_arg: *mut c_void, bserrno: c_int) {
+    if bserrno != 0 {
+        error!("close blob error: {}", bserrno);
+    }
+}
+
+/// `SPDK_BLOBID_INVALID`
+const BLOBID_INVALID: spdk_blob_id = spdk_blob_id::MAX;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct BlobId {
     id: spdk_blob_id,
 }
 
+impl BlobId {
+    pub(crate) fn from_raw(id: spdk_blob_id) -> Self {
+        BlobId { id }
+    }
+}
+
 impl Default for BlobId {
     fn default() -> Self {
         Self { id: 0 }
@@ -209,6 +607,231 @@ impl fmt::Display for BlobId {
     }
 }
 
+/// Options for initializing or loading a blobstore.
+///
+/// Unset options use the SPDK defaults.
+#[derive(Debug, Clone, Default)]
+pub struct BlobstoreOpts {
+    cluster_size: Option<u32>,
+    num_md_pages: Option<u32>,
+    max_channel_ops: Option<u32>,
+    bstype: Option<String>,
+}
+
+impl BlobstoreOpts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the cluster size in bytes. Only used on init.
+    pub fn cluster_size(mut self, cluster_size: u32) -> Self {
+        self.cluster_size = Some(cluster_size);
+        self
+    }
+
+    /// Set the number of metadata pages. Only used on init.
+    pub fn num_md_pages(mut self, num_md_pages: u32) -> Self {
+        self.num_md_pages = Some(num_md_pages);
+        self
+    }
+
+    /// Set the maximum number of outstanding operations per I/O channel.
+    pub fn max_channel_ops(mut self, max_channel_ops: u32) -> Self {
+        self.max_channel_ops = Some(max_channel_ops);
+        self
+    }
+
+    /// Set the blobstore type, at most 15 bytes.
+    ///
+    /// It is written on init, and checked on load.
+    pub fn bstype(mut self, bstype: &str) -> Self {
+        self.bstype = Some(bstype.into());
+        self
+    }
+
+    /// Initialize a blobstore on the given device.
+    pub async fn init(&self, bs_dev: &mut BlobStoreBDev) -> Result<Blobstore> {
+        let mut opts = self.to_raw().with_op("initialize blobstore")?;
+        let ptr = do_async(|arg| unsafe {
+            spdk_bs_init(bs_dev.ptr, &mut opts, Some(callback_with), arg);
+        })
+        .await
+        .with_op("initialize blobstore")?;
+        bs_dev.set_blobstore(ptr);
+        Ok(Blobstore { ptr })
+    }
+
+    /// Load a blobstore on the given device.
+    ///
+    /// Fails with `ENXIO` if a bstype is set and the blobstore has a different one.
+    pub async fn load(&self, bs_dev: &mut BlobStoreBDev) -> Result<Blobstore> {
+        let mut opts = self.to_raw().with_op("load blobstore")?;
+        let ptr = do_async(|arg| unsafe {
+            spdk_bs_load(bs_dev.ptr, &mut opts, Some(callback_with), arg);
+        })
+        .await
+        .with_op("load blobstore")?;
+        bs_dev.set_blobstore(ptr);
+        Ok(Blobstore { ptr })
+    }
+
+    /// Load a blobstore and grow it to fill the device.
+    pub async fn load_and_grow(&self, bs_dev: &mut BlobStoreBDev) -> Result<Blobstore> {
+        let mut opts = self.to_raw().with_op("grow blobstore")?;
+        let ptr = do_async(|arg| unsafe {
+            spdk_bs_grow(bs_dev.ptr, &mut opts, Some(callback_with), arg);
+        })
+        .await
+        .with_op("grow blobstore")?;
+        bs_dev.set_blobstore(ptr);
+        Ok(Blobstore { ptr })
+    }
+
+    fn to_raw(&self) -> Result<spdk_bs_opts> {
+        let mut opts = MaybeUninit::uninit();
+        let mut opts = unsafe {
+            spdk_bs_opts_init(opts.as_mut_ptr(), size_of::<spdk_bs_opts>() as _);
+            opts.assume_init()
+        };
+        if let Some(cluster_size) = self.cluster_size {
+            opts.cluster_sz = cluster_size;
+        }
+        if let Some(num_md_pages) = self.num_md_pages {
+            opts.num_md_pages = num_md_pages;
+        }
+        if let Some(max_channel_ops) = self.max_channel_ops {
+            opts.max_channel_ops = max_channel_ops;
+        }
+        if let Some(bstype) = &self.bstype {
+            // keep a trailing NUL
+            if bstype.len() >= opts.bstype.bstype.len() || bstype.contains('\0') {
+                return Err(SpdkError::from(-(EINVAL as i32)).with_object(bstype));
+            }
+            for (dst, &src) in opts.bstype.bstype.iter_mut().zip(bstype.as_bytes()) {
+                *dst = src as c_char;
+            }
+        }
+        Ok(opts)
+    }
+}
+
+/// Encoded xattrs of a blob to create.
+type XattrList = Vec<(CString, Vec<u8>)>;
+
+/// Options for creating a blob with `Blobstore::create_blob_ext`.
+#[derive(Debug, Clone, Default)]
+pub struct BlobOpts {
+    num_clusters: u64,
+    thin_provision: bool,
+    clear_method: BlobClearMethod,
+    xattrs: XattrList,
+}
+
+impl BlobOpts {
+    /// Default options: empty, thick provisioned blob without xattrs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the initial size in clusters.
+    pub fn num_clusters(mut self, num_clusters: u64) -> Self {
+        self.num_clusters = num_clusters;
+        self
+    }
+
+    /// Allocate clusters on first write instead of at creation.
+    pub fn thin_provision(mut self, thin_provision: bool) -> Self {
+        self.thin_provision = thin_provision;
+        self
+    }
+
+    /// Set how clusters are cleared when the blob is resized down or deleted.
+    pub fn clear_method(mut self, clear_method: BlobClearMethod) -> Self {
+        self.clear_method = clear_method;
+        self
+    }
+
+    /// Set an initial xattr, encoded as JSON like `Blob::set_xattr`.
+    pub fn xattr<T: Serialize + ?Sized>(mut self, name: &str, value: &T) -> Result<Self> {
+        let value = encode_xattr(name, value)?;
+        let cname = xattr_name(name)?;
+        self.xattrs.retain(|(n, _)| *n != cname);
+        self.xattrs.push((cname, value));
+        Ok(self)
+    }
+}
+
+/// Extended options for `Blob::readv_ext` and `Blob::writev_ext`.
+#[derive(Debug, Clone)]
+pub struct BlobExtIoOpts {
+    memory_domain: *mut spdk_memory_domain,
+    memory_domain_ctx: *mut c_void,
+}
+
+impl Default for BlobExtIoOpts {
+    fn default() -> Self {
+        Self {
+            memory_domain: std::ptr::null_mut(),
+            memory_domain_ctx: std::ptr::null_mut(),
+        }
+    }
+}
+
+impl BlobExtIoOpts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the memory domain the buffers belong to.
+    ///
+    /// # Safety
+    ///
+    /// `memory_domain` and `ctx` must describe the buffers of every I/O
+    /// issued with these options and stay valid until it completes.
+    pub unsafe fn memory_domain(
+        mut self,
+        memory_domain: *mut spdk_memory_domain,
+        ctx: *mut c_void,
+    ) -> Self {
+        self.memory_domain = memory_domain;
+        self.memory_domain_ctx = ctx;
+        self
+    }
+
+    fn to_raw(&self) -> spdk_blob_ext_io_opts {
+        let mut opts: spdk_blob_ext_io_opts = unsafe { std::mem::zeroed() };
+        opts.size = size_of::<spdk_blob_ext_io_opts>() as _;
+        opts.memory_domain = self.memory_domain;
+        opts.memory_domain_ctx = self.memory_domain_ctx;
+        opts
+    }
+}
+
+/// How the clusters of a blob are cleared when they are released.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BlobClearMethod {
+    /// Use the blobstore default.
+    #[default]
+    Default,
+    /// Do not clear.
+    None,
+    /// Unmap the clusters.
+    Unmap,
+    /// Write zeroes to the clusters.
+    WriteZeroes,
+}
+
+impl BlobClearMethod {
+    fn as_raw(self) -> blob_clear_method {
+        match self {
+            BlobClearMethod::Default => blob_clear_method_BLOB_CLEAR_WITH_DEFAULT,
+            BlobClearMethod::None => blob_clear_method_BLOB_CLEAR_WITH_NONE,
+            BlobClearMethod::Unmap => blob_clear_method_BLOB_CLEAR_WITH_UNMAP,
+            BlobClearMethod::WriteZeroes => blob_clear_method_BLOB_CLEAR_WITH_WRITE_ZEROES,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct IoChannel {
     pub ptr: *mut spdk_io_channel,
@@ -223,54 +846,219 @@ impl Drop for IoChannel {
     }
 }
 
+/// An open blob of the blobstore `'bs`.
+///
+/// Clones share the open blob. It is closed when the last clone is dropped,
+/// on the SPDK thread that opened it, or explicitly by `close`.
+/// A blob stays on its thread, use `RemoteBlob` from other threads.
+#[derive(Debug, Clone)]
+pub struct Blob<'bs> {
+    inner: Rc<BlobInner>,
+    _bs: PhantomData<&'bs Blobstore>,
+}
+
 #[derive(Debug)]
-pub struct Blob {
-    pub ptr: *mut spdk_blob,
+struct BlobInner {
+    ptr: *mut spdk_blob,
+    bs: *mut spdk_blob_store,
     io_unit_size: u64,
+    /// Thread that opened the blob.
+    thread: *mut spdk_thread,
 }
 
-impl Default for Blob {
-    fn default() -> Self {
-        Self {
-            ptr: std::ptr::null_mut(),
-            io_unit_size: 512,
+impl Drop for BlobInner {
+    fn drop(&mut self) {
+        if self.ptr.is_null() {
+            return;
+        }
+        let current = unsafe { spdk_get_thread() };
+        if self.thread.is_null() || self.thread == current {
+            close_detached(self.ptr as _);
+            return;
+        }
+        let rc = unsafe { spdk_thread_send_msg(self.thread, Some(close_detached), self.ptr as _) };
+        if rc != 0 {
+            error!("failed to schedule blob close: {}", rc);
         }
     }
 }
 
-unsafe impl Send for Blob {}
-unsafe impl Sync for Blob {}
-
-impl Clone for Blob {
-    fn clone(&self) -> Self {
-        Self {
-            ptr: self.ptr.clone(),
-            io_unit_size: self.io_unit_size,
+impl<'bs> Blob<'bs> {
+    /// Take ownership of an open blob, opened on the current thread.
+    fn from_raw(ptr: *mut spdk_blob, bs: *mut spdk_blob_store, io_unit_size: u64) -> Self {
+        Blob {
+            inner: Rc::new(BlobInner {
+                ptr,
+                bs,
+                io_unit_size,
+                thread: unsafe { spdk_get_thread() },
+            }),
+            _bs: PhantomData,
         }
     }
-}
 
-impl Copy for Blob {}
+    /// Get the raw blob pointer, valid while this handle lives.
+    pub fn as_ptr(&self) -> *mut spdk_blob {
+        self.inner.ptr
+    }
 
-impl Blob {
     /// Get the number of clusters allocated to the blob.
     pub fn num_clusters(&self) -> u64 {
-        unsafe { spdk_blob_get_num_clusters(self.ptr) }
+        unsafe { spdk_blob_get_num_clusters(self.inner.ptr) }
+    }
+
+    /// Get the size of the blob in io units.
+    pub fn num_io_units(&self) -> u64 {
+        unsafe { spdk_blob_get_num_io_units(self.inner.ptr) }
+    }
+
+    /// Whether the cluster at `index` is allocated to this blob.
+    ///
+    /// Clusters of a thin provisioned blob are allocated on first write.
+    /// Clusters only provided by the parent snapshot are not allocated,
+    /// and `false` is returned if `index` is out of range.
+    pub fn is_cluster_allocated(&self, index: u64) -> bool {
+        if index >= self.num_clusters() {
+            return false;
+        }
+        let units = self.units_per_cluster();
+        let start = match index.checked_mul(units) {
+            Some(start) => start,
+            None => return false,
+        };
+        let next = unsafe { spdk_blob_get_next_allocated_io_unit(self.inner.ptr, start) };
+        // `u64::MAX` when there is no allocated io unit left
+        next.checked_sub(start).is_some_and(|offset| offset < units)
+    }
+
+    /// Iterate over the allocated extents of the blob, as ranges of io units.
+    ///
+    /// Holes of a thin provisioned blob are skipped, extents are at cluster granularity.
+    pub fn allocated_extents(&self) -> AllocatedExtents<'_, 'bs> {
+        AllocatedExtents {
+            blob: self,
+            offset: 0,
+            end: self.num_io_units(),
+        }
+    }
+
+    fn units_per_cluster(&self) -> u64 {
+        unsafe { spdk_bs_get_cluster_size(self.inner.bs) / self.inner.io_unit_size }
     }
 
     /// Get the blob id.
     pub fn blob_id(&self) -> BlobId {
-        let id = unsafe { spdk_blob_get_id(self.ptr) };
+        let id = unsafe { spdk_blob_get_id(self.inner.ptr) };
         BlobId { id }
     }
 
+    /// Whether the blob is read-only, e.g. a snapshot.
+    pub fn is_read_only(&self) -> bool {
+        unsafe { spdk_blob_is_read_only(self.inner.ptr) }
+    }
+
+    /// Whether the blob is a snapshot.
+    pub fn is_snapshot(&self) -> bool {
+        unsafe { spdk_blob_is_snapshot(self.inner.ptr) }
+    }
+
+    /// Whether the blob is a clone of a snapshot.
+    pub fn is_clone(&self) -> bool {
+        unsafe { spdk_blob_is_clone(self.inner.ptr) }
+    }
+
+    /// Whether clusters of the blob are allocated on first write.
+    pub fn is_thin_provisioned(&self) -> bool {
+        unsafe { spdk_blob_is_thin_provisioned(self.inner.ptr) }
+    }
+
+    /// Get the snapshot this blob was cloned from, if any.
+    pub fn parent_snapshot(&self) -> Option<BlobId> {
+        self.blobstore().parent_snapshot(self.blob_id())
+    }
+
+    /// Get the clones of this snapshot.
+    pub fn clones(&self) -> Result<Vec<BlobId>> {
+        self.blobstore().clones(self.blob_id())
+    }
+
+    fn blobstore(&self) -> Blobstore {
+        Blobstore { ptr: self.inner.bs }
+    }
+
+    /// Set an extended attribute, encoded as JSON.
+    ///
+    /// Replaces any existing value. The change is not persistent until
+    /// `sync_metadata` is called.
+    pub fn set_xattr<T: Serialize + ?Sized>(&self, name: &str, value: &T) -> Result<()> {
+        let value = encode_xattr(name, value)?;
+        let cname = xattr_name(name)?;
+        let err = unsafe {
+            spdk_blob_set_xattr(
+                self.inner.ptr,
+                cname.as_ptr(),
+                value.as_ptr() as _,
+                value.len() as u16,
+            )
+        };
+        SpdkError::from_retval(err)
+            .with_op("set xattr")
+            .with_object(name)
+    }
+
+    /// Get an extended attribute, or `None` if it does not exist.
+    pub fn get_xattr<T: DeserializeOwned>(&self, name: &str) -> Result<Option<T>> {
+        let cname = xattr_name(name)?;
+        let mut value = std::ptr::null();
+        let mut len = 0;
+        let err = unsafe {
+            spdk_blob_get_xattr_value(self.inner.ptr, cname.as_ptr(), &mut value, &mut len)
+        };
+        if err == -(ENOENT as i32) {
+            return Ok(None);
+        }
+        SpdkError::from_retval(err)
+            .with_op("get xattr")
+            .with_object(name)?;
+        let value = unsafe { std::slice::from_raw_parts(value as *const u8, len as usize) };
+        serde_json::from_slice(value)
+            .map(Some)
+            .map_err(|_| xattr_error("decode xattr", name))
+    }
+
+    /// Remove an extended attribute.
+    ///
+    /// Fails with `ENOENT` if it does not exist. The change is not persistent
+    /// until `sync_metadata` is called.
+    pub fn remove_xattr(&self, name: &str) -> Result<()> {
+        let cname = xattr_name(name)?;
+        let err = unsafe { spdk_blob_remove_xattr(self.inner.ptr, cname.as_ptr()) };
+        SpdkError::from_retval(err)
+            .with_op("remove xattr")
+            .with_object(name)
+    }
+
+    /// Get the names of all extended attributes.
+    pub fn xattr_names(&self) -> Result<Vec<String>> {
+        let mut names = std::ptr::null_mut();
+        let err = unsafe { spdk_blob_get_xattr_names(self.inner.ptr, &mut names) };
+        SpdkError::from_retval(err).with_op("list xattrs")?;
+        let count = unsafe { spdk_xattr_names_get_count(names) };
+        let list = (0..count)
+            .map(|i| unsafe { CStr::from_ptr(spdk_xattr_names_get_name(names, i)) })
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect();
+        unsafe { spdk_xattr_names_free(names) };
+        Ok(list)
+    }
+
     /// Read data from a blob.
     pub async fn read(&self, io_channel: &IoChannel, offset: u64, buf: &mut [u8]) -> Result<()> {
-        assert_eq!(buf.len() as u64 % self.io_unit_size, 0);
-        let units = buf.len() as u64 / self.io_unit_size;
+        assert_eq!(buf.len() as u64 % self.inner.io_unit_size, 0);
+        let units = buf.len() as u64 / self.inner.io_unit_size;
         do_async(|arg| unsafe {
             spdk_blob_io_read(
-                self.ptr,
+                self.inner.ptr,
                 io_channel.ptr,
                 buf.as_mut_ptr() as _,
                 offset,
@@ -280,9 +1068,12 @@ impl Blob {
             );
         })
         .await
+        .with_op("read blob")
+        .with_object(self.blob_id())
     }
 
     /// Read data from a blob, sync API
+    #[deprecated(note = "use `BlobstoreHandle` instead")]
     pub fn read_sync(
         &self,
         io_channel: &IoChannel,
@@ -290,11 +1081,11 @@ impl Blob {
         buf: &mut [u8],
         cb_arg: *mut c_void,
     ) -> Result<()> {
-        assert_eq!(buf.len() as u64 % self.io_unit_size, 0);
-        let units = buf.len() as u64 / self.io_unit_size;
+        assert_eq!(buf.len() as u64 % self.inner.io_unit_size, 0);
+        let units = buf.len() as u64 / self.inner.io_unit_size;
         unsafe {
             spdk_blob_io_read(
-                self.ptr,
+                self.inner.ptr,
                 io_channel.ptr,
                 buf.as_mut_ptr() as _,
                 offset,
@@ -308,11 +1099,11 @@ impl Blob {
 
     /// Write data to a blob.
     pub async fn write(&self, io_channel: &IoChannel, offset: u64, buf: &[u8]) -> Result<()> {
-        assert_eq!(buf.len() as u64 % self.io_unit_size, 0);
-        let units = buf.len() as u64 / self.io_unit_size;
+        assert_eq!(buf.len() as u64 % self.inner.io_unit_size, 0);
+        let units = buf.len() as u64 / self.inner.io_unit_size;
         do_async(|arg| unsafe {
             spdk_blob_io_write(
-                self.ptr,
+                self.inner.ptr,
                 io_channel.ptr,
                 buf.as_ptr() as _,
                 offset,
@@ -322,9 +1113,12 @@ impl Blob {
             );
         })
         .await
+        .with_op("write blob")
+        .with_object(self.blob_id())
     }
 
     /// Write data to a blob, sync API
+    #[deprecated(note = "use `BlobstoreHandle` instead")]
     pub fn write_sync(
         &self,
         io_channel: &IoChannel,
@@ -332,11 +1126,11 @@ impl Blob {
         buf: &[u8],
         cb_arg: *mut c_void,
     ) -> Result<()> {
-        assert_eq!(buf.len() as u64 % self.io_unit_size, 0);
-        let units = buf.len() as u64 / self.io_unit_size;
+        assert_eq!(buf.len() as u64 % self.inner.io_unit_size, 0);
+        let units = buf.len() as u64 / self.inner.io_unit_size;
         unsafe {
             spdk_blob_io_write(
-                self.ptr,
+                self.inner.ptr,
                 io_channel.ptr,
                 buf.as_ptr() as _,
                 offset,
@@ -350,15 +1144,25 @@ impl Blob {
 
     /// Write zeros into area of a blob.
     pub async fn write_zero(&self, io_channel: &IoChannel, offset: u64, len: u64) -> Result<()> {
-        assert_eq!(len % self.io_unit_size, 0);
-        let units = len / self.io_unit_size;
+        assert_eq!(len % self.inner.io_unit_size, 0);
+        let units = len / self.inner.io_unit_size;
         do_async(|arg| unsafe {
-            spdk_blob_io_write_zeroes(self.ptr, io_channel.ptr, offset, units, Some(callback), arg);
+            spdk_blob_io_write_zeroes(
+                self.inner.ptr,
+                io_channel.ptr,
+                offset,
+                units,
+                Some(callback),
+                arg,
+            );
         })
         .await
+        .with_op("write zeroes to blob")
+        .with_object(self.blob_id())
     }
 
     /// Write zeros to a blob, sync API
+    #[deprecated(note = "use `BlobstoreHandle` instead")]
     pub fn write_zero_sync(
         &self,
         io_channel: &IoChannel,
@@ -366,11 +1170,11 @@ impl Blob {
         len: u64,
         cb_arg: *mut c_void,
     ) -> Result<()> {
-        assert_eq!(len % self.io_unit_size, 0);
-        let units = len / self.io_unit_size;
+        assert_eq!(len % self.inner.io_unit_size, 0);
+        let units = len / self.inner.io_unit_size;
         unsafe {
             spdk_blob_io_write_zeroes(
-                self.ptr,
+                self.inner.ptr,
                 io_channel.ptr,
                 offset,
                 units,
@@ -381,19 +1185,160 @@ impl Blob {
         Ok(())
     }
 
+    /// Read data from a blob into a scatter-gather list.
+    ///
+    /// `offset` is in io units, the length is the total length of `iov`.
+    pub async fn readv(
+        &self,
+        io_channel: &IoChannel,
+        offset: u64,
+        iov: &mut IoVec<'_>,
+    ) -> Result<()> {
+        iov.check_writable().with_object(self.blob_id())?;
+        let units = self.iov_units(iov)?;
+        do_async(|arg| unsafe {
+            spdk_blob_io_readv(
+                self.inner.ptr,
+                io_channel.ptr,
+                iov.as_mut_ptr(),
+                iov.iovcnt(),
+                offset,
+                units,
+                Some(callback),
+                arg,
+            );
+        })
+        .await
+        .with_op("read blob")
+        .with_object(self.blob_id())
+    }
+
+    /// Write data to a blob from a scatter-gather list.
+    ///
+    /// `offset` is in io units, the length is the total length of `iov`.
+    pub async fn writev(&self, io_channel: &IoChannel, offset: u64, iov: &IoVec<'_>) -> Result<()> {
+        let units = self.iov_units(iov)?;
+        do_async(|arg| unsafe {
+            spdk_blob_io_writev(
+                self.inner.ptr,
+                io_channel.ptr,
+                iov.as_ptr() as _,
+                iov.iovcnt(),
+                offset,
+                units,
+                Some(callback),
+                arg,
+            );
+        })
+        .await
+        .with_op("write blob")
+        .with_object(self.blob_id())
+    }
+
+    /// Like `readv`, with extended I/O options.
+    pub async fn readv_ext(
+        &self,
+        io_channel: &IoChannel,
+        offset: u64,
+        iov: &mut IoVec<'_>,
+        opts: &BlobExtIoOpts,
+    ) -> Result<()> {
+        iov.check_writable().with_object(self.blob_id())?;
+        let units = self.iov_units(iov)?;
+        let mut raw = opts.to_raw();
+        do_async(|arg| unsafe {
+            spdk_blob_io_readv_ext(
+                self.inner.ptr,
+                io_channel.ptr,
+                iov.as_mut_ptr(),
+                iov.iovcnt(),
+                offset,
+                units,
+                Some(callback),
+                arg,
+                &mut raw,
+            );
+        })
+        .await
+        .with_op("read blob")
+        .with_object(self.blob_id())
+    }
+
+    /// Like `writev`, with extended I/O options.
+    pub async fn writev_ext(
+        &self,
+        io_channel: &IoChannel,
+        offset: u64,
+        iov: &IoVec<'_>,
+        opts: &BlobExtIoOpts,
+    ) -> Result<()> {
+        let units = self.iov_units(iov)?;
+        let mut raw = opts.to_raw();
+        do_async(|arg| unsafe {
+            spdk_blob_io_writev_ext(
+                self.inner.ptr,
+                io_channel.ptr,
+                iov.as_ptr() as _,
+                iov.iovcnt(),
+                offset,
+                units,
+                Some(callback),
+                arg,
+                &mut raw,
+            );
+        })
+        .await
+        .with_op("write blob")
+        .with_object(self.blob_id())
+    }
+
+    /// Unmap `len` bytes at `offset` io units.
+    ///
+    /// Clusters of a thin provisioned blob that are fully unmapped are released.
+    pub async fn unmap(&self, io_channel: &IoChannel, offset: u64, len: u64) -> Result<()> {
+        if !len.is_multiple_of(self.inner.io_unit_size) {
+            return Err(SpdkError::from(-(EINVAL as i32)).with_op("unmap blob"));
+        }
+        let units = len / self.inner.io_unit_size;
+        do_async(|arg| unsafe {
+            spdk_blob_io_unmap(
+                self.inner.ptr,
+                io_channel.ptr,
+                offset,
+                units,
+                Some(callback),
+                arg,
+            );
+        })
+        .await
+        .with_op("unmap blob")
+        .with_object(self.blob_id())
+    }
+
+    /// Get the length of `iov` in io units, failing if it is empty or not aligned.
+    fn iov_units(&self, iov: &IoVec<'_>) -> Result<u64> {
+        if iov.is_empty() || !iov.len().is_multiple_of(self.inner.io_unit_size) {
+            return Err(SpdkError::from(-(EINVAL as i32)).with_object(self.blob_id()));
+        }
+        Ok(iov.len() / self.inner.io_unit_size)
+    }
+
     /// Resize a blob to `size` clusters.
     ///
     /// These changes are not persisted to disk until spdk_bs_md_sync_blob() is called.
     /// If called before previous resize finish, it will fail with errno -EBUSY.
     pub async fn resize(&self, size: u64) -> Result<()> {
         do_async(|arg| unsafe {
-            spdk_blob_resize(self.ptr, size, Some(callback), arg);
+            spdk_blob_resize(self.inner.ptr, size, Some(callback), arg);
         })
-        .await?;
+        .await
+        .with_op("resize blob")
+        .with_object(self.blob_id())?;
         Ok(())
     }
 
     /// Resize a blob, sync API
+    #[deprecated(note = "use `BlobstoreHandle` instead")]
     pub fn resize_sync(
         &self,
         size: u64,
@@ -401,7 +1346,7 @@ impl Blob {
         cb_arg: *mut c_void,
     ) -> Result<()> {
         unsafe {
-            spdk_blob_resize(self.ptr, size, Some(resize_callback), cb_arg);
+            spdk_blob_resize(self.inner.ptr, size, Some(resize_callback), cb_arg);
         }
         Ok(())
     }
@@ -412,42 +1357,504 @@ impl Blob {
     /// These operations will not be persistent until the blob has been synced.
     pub async fn sync_metadata(&self) -> Result<()> {
         do_async(|arg| unsafe {
-            spdk_blob_sync_md(self.ptr, Some(callback), arg);
+            spdk_blob_sync_md(self.inner.ptr, Some(callback), arg);
         })
-        .await?;
+        .await
+        .with_op("sync blob metadata")
+        .with_object(self.blob_id())?;
         Ok(())
     }
 
     /// Sync blob's metadata, sync API
+    #[deprecated(note = "use `BlobstoreHandle` instead")]
     pub fn sync_metadata_sync(
         &self,
         // cb_fn: unsafe extern "C" fn(*mut c_void, c_int),
         cb_arg: *mut c_void,
     ) -> Result<()> {
         unsafe {
-            spdk_blob_sync_md(self.ptr, Some(sync_md_callback), cb_arg);
+            spdk_blob_sync_md(self.inner.ptr, Some(sync_md_callback), cb_arg);
         }
         Ok(())
     }
 
     /// Close a blob.
     ///
-    /// This will automatically sync.
+    /// This will automatically sync. If other clones of this handle exist,
+    /// only this one is released and the blob stays open.
+    /// Called off the thread that opened the blob, the close is forwarded to it.
     pub async fn close(self) -> Result<()> {
-        do_async(|arg| unsafe {
-            spdk_blob_close(self.ptr, Some(callback), arg);
+        let (ptr, thread) = match self.take_last() {
+            Some(last) => last,
+            None => return Ok(()),
+        };
+        if thread.is_null() || thread == unsafe { spdk_get_thread() } {
+            return close_blob(ptr).await;
+        }
+        let (tx, rx) = oneshot::channel();
+        let ptr = ptr as usize;
+        send_msg(thread, move || {
+            event::spawn(async move {
+                let _ = tx.send(close_blob(ptr as _).await);
+            });
         })
-        .await?;
-        Ok(())
+        .with_op("close blob")?;
+        match rx.await {
+            Ok(result) => result,
+            Err(_) => Err(SpdkError::from(-(ECANCELED as i32)).with_op("close blob")),
+        }
     }
 
     /// Close a blob, sync API
+    #[deprecated(note = "use `BlobstoreHandle` instead")]
     pub fn close_sync(self, cb_arg: *mut c_void) -> Result<()> {
-        unsafe {
-            spdk_blob_close(self.ptr, Some(close_blob_callback), cb_arg);
+        let (ptr, thread) = match self.take_last() {
+            Some(last) => last,
+            None => {
+                close_blob_callback(cb_arg, 0);
+                return Ok(());
+            }
+        };
+        if thread.is_null() || thread == unsafe { spdk_get_thread() } {
+            unsafe { spdk_blob_close(ptr, Some(close_blob_callback), cb_arg) };
+            return Ok(());
+        }
+        let (ptr, cb_arg) = (ptr as usize, cb_arg as usize);
+        send_msg(thread, move || unsafe {
+            spdk_blob_close(ptr as _, Some(close_blob_callback), cb_arg as _);
+        })
+        .with_op("close blob")
+    }
+
+    /// Drop the borrow of the blobstore, for handles managed by a `BlobstoreHandle`.
+    fn into_owned(self) -> Blob<'static> {
+        Blob {
+            inner: self.inner,
+            _bs: PhantomData,
+        }
+    }
+
+    /// Take the blob and its owning thread out of the last handle,
+    /// so that dropping it does not close the blob.
+    fn take_last(self) -> Option<(*mut spdk_blob, *mut spdk_thread)> {
+        let mut inner = Rc::try_unwrap(self.inner).ok()?;
+        let ptr = std::mem::replace(&mut inner.ptr, std::ptr::null_mut());
+        if ptr.is_null() {
+            return None;
+        }
+        Some((ptr, inner.thread))
+    }
+}
+
+/// Close a blob on the current thread, which must own it.
+async fn close_blob(ptr: *mut spdk_blob) -> Result<()> {
+    let blob_id = BlobId {
+        id: unsafe { spdk_blob_get_id(ptr) },
+    };
+    do_async(|arg| unsafe {
+        spdk_blob_close(ptr, Some(callback), arg);
+    })
+    .await
+    .with_op("close blob")
+    .with_object(blob_id)
+}
+
+/// Handle to a blobstore that can be used from any thread, see `Blobstore::handle`.
+///
+/// Each request runs on the SPDK thread that owns the blobstore, and the
+/// returned futures can be awaited on any executor. Once unloaded through
+/// any clone, requests of all clones fail with `ENODEV`.
+#[derive(Debug, Clone)]
+pub struct BlobstoreHandle {
+    state: Arc<HandleState>,
+}
+
+/// State shared by the clones of a `BlobstoreHandle`.
+#[derive(Debug)]
+struct HandleState {
+    bs: *mut spdk_blob_store,
+    thread: *mut spdk_thread,
+    io_unit_size: u64,
+    /// Only accessed on the owning thread.
+    unloaded: AtomicBool,
+}
+
+unsafe impl Send for HandleState {}
+unsafe impl Sync for HandleState {}
+
+impl BlobstoreHandle {
+    /// Get the io unit size in bytes.
+    pub fn io_unit_size(&self) -> u64 {
+        self.state.io_unit_size
+    }
+
+    /// Get the number of free clusters.
+    pub async fn free_cluster_count(&self) -> Result<u64> {
+        self.call(|bs| async move { Ok(bs.free_cluster_count()) })
+            .await
+    }
+
+    /// Create a new blob with default option values.
+    pub async fn create_blob(&self) -> Result<BlobId> {
+        self.call(|bs| async move { bs.create_blob().await }).await
+    }
+
+    /// Create a new blob with the given options.
+    pub async fn create_blob_ext(&self, opts: BlobOpts) -> Result<BlobId> {
+        self.call(|bs| async move { bs.create_blob_ext(&opts).await })
+            .await
+    }
+
+    /// Open a blob, along with an I/O channel for it on the owning thread.
+    pub async fn open_blob(&self, blob_id: BlobId) -> Result<RemoteBlob> {
+        let (blob, channel) = self
+            .call(move |bs| async move {
+                let blob = bs.open_blob(blob_id).await?.into_owned();
+                let channel = bs.alloc_io_channel()?;
+                Ok((SendBlob(Some(blob)), channel))
+            })
+            .await?;
+        Ok(RemoteBlob {
+            id: blob_id,
+            blob: Some(Arc::new(blob)),
+            channel: Some(Arc::new(channel)),
+            handle: self.clone(),
+        })
+    }
+
+    /// Delete a blob, which must not be open.
+    pub async fn delete_blob(&self, blob_id: BlobId) -> Result<()> {
+        self.call(move |bs| async move { bs.delete_blob(blob_id).await })
+            .await
+    }
+
+    /// Unload the blobstore.
+    ///
+    /// All blobs opened through handles must be dropped before.
+    pub async fn unload(self) -> Result<()> {
+        let state = self.state.clone();
+        self.call(move |bs| {
+            state.unloaded.store(true, Ordering::Relaxed);
+            async move { bs.unload().await }
+        })
+        .await
+    }
+
+    /// Run `f` on the owning thread and wait for its result.
+    async fn call<T, F, Fut>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(Blobstore) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<T>> + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let state = self.state.clone();
+        self.send(move || {
+            // checked and set on the owning thread, so no request starts after unload
+            if state.unloaded.load(Ordering::Relaxed) {
+                let err = SpdkError::from(-(ENODEV as i32)).with_op("blobstore request");
+                let _ = tx.send(Err(err));
+                return;
+            }
+            let fut = f(Blobstore { ptr: state.bs });
+            event::spawn(async move {
+                // the caller may have given up waiting
+                let _ = tx.send(fut.await);
+            });
+        })?;
+        match rx.await {
+            Ok(result) => result,
+            Err(_) => Err(SpdkError::from(-(ECANCELED as i32)).with_op("blobstore request")),
+        }
+    }
+
+    /// Run `f` on the owning thread without waiting for it.
+    fn send(&self, f: impl FnOnce() + Send + 'static) -> Result<()> {
+        send_msg(self.state.thread, f)
+    }
+}
+
+/// Run `f` on an SPDK thread without waiting for it.
+fn send_msg(thread: *mut spdk_thread, f: impl FnOnce() + Send + 'static) -> Result<()> {
+    let msg: Box<dyn FnOnce() + Send> = Box::new(f);
+    let arg = Box::into_raw(Box::new(msg));
+    let rc = unsafe { spdk_thread_send_msg(thread, Some(run_msg), arg as _) };
+    if rc != 0 {
+        unsafe { drop(Box::from_raw(arg)) };
+        return Err(SpdkError::from(rc).with_op("send message"));
+    }
+    Ok(())
+}
+
+extern "C" fn run_msg(arg: *mut c_void) {
+    let f = unsafe { Box::from_raw(arg as *mut Box<dyn FnOnce() + Send>) };
+    f();
+}
+
+/// An open blob used through a `BlobstoreHandle`.
+///
+/// It is closed when dropped, or explicitly by `close`.
+#[derive(Debug)]
+pub struct RemoteBlob {
+    id: BlobId,
+    /// Taken on close.
+    blob: Option<Arc<SendBlob>>,
+    /// Channel of the owning thread, freed there.
+    channel: Option<Arc<IoChannel>>,
+    handle: BlobstoreHandle,
+}
+
+impl RemoteBlob {
+    /// Get the blob id.
+    pub fn blob_id(&self) -> BlobId {
+        self.id
+    }
+
+    /// Get the number of clusters allocated to the blob.
+    pub async fn num_clusters(&self) -> Result<u64> {
+        let blob = self.blob();
+        self.handle
+            .call(move |_| async move { Ok(blob.get()?.num_clusters()) })
+            .await
+    }
+
+    /// Read data from the blob into `buf`, which is given back on success.
+    ///
+    /// `offset` is in io units.
+    pub async fn read(&self, offset: u64, mut buf: DmaBuf) -> Result<DmaBuf> {
+        self.check_len(buf.as_ref().len() as u64)
+            .with_op("read blob")?;
+        let (blob, channel) = self.parts();
+        self.handle
+            .call(move |_| async move {
+                blob.get()?.read(&channel, offset, buf.as_mut()).await?;
+                Ok(buf)
+            })
+            .await
+    }
+
+    /// Write `buf` to the blob, which is given back on success.
+    ///
+    /// `offset` is in io units.
+    pub async fn write(&self, offset: u64, buf: DmaBuf) -> Result<DmaBuf> {
+        self.check_len(buf.as_ref().len() as u64)
+            .with_op("write blob")?;
+        let (blob, channel) = self.parts();
+        self.handle
+            .call(move |_| async move {
+                blob.get()?.write(&channel, offset, buf.as_ref()).await?;
+                Ok(buf)
+            })
+            .await
+    }
+
+    /// Write zeros into `len` bytes at `offset` io units.
+    pub async fn write_zero(&self, offset: u64, len: u64) -> Result<()> {
+        self.check_len(len).with_op("write zeroes to blob")?;
+        let (blob, channel) = self.parts();
+        self.handle
+            .call(move |_| async move { blob.get()?.write_zero(&channel, offset, len).await })
+            .await
+    }
+
+    /// Resize the blob to `size` clusters, see `Blob::resize`.
+    pub async fn resize(&self, size: u64) -> Result<()> {
+        let blob = self.blob();
+        self.handle
+            .call(move |_| async move { blob.get()?.resize(size).await })
+            .await
+    }
+
+    /// Persist the metadata of the blob.
+    pub async fn sync_metadata(&self) -> Result<()> {
+        let blob = self.blob();
+        self.handle
+            .call(move |_| async move { blob.get()?.sync_metadata().await })
+            .await
+    }
+
+    /// Close the blob, see `Blob::close`.
+    pub async fn close(mut self) -> Result<()> {
+        let blob = self.blob.take();
+        let channel = self.channel.take();
+        self.handle
+            .call(move |_| async move {
+                drop(channel);
+                // requests in flight keep the blob open until they complete
+                match blob.map(Arc::try_unwrap) {
+                    Some(Ok(blob)) => blob.into_inner()?.close().await,
+                    _ => Ok(()),
+                }
+            })
+            .await
+    }
+
+    /// Fail instead of panicking on the owning thread if `len` is not aligned.
+    fn check_len(&self, len: u64) -> Result<()> {
+        if !len.is_multiple_of(self.handle.io_unit_size()) {
+            return Err(SpdkError::from(-(EINVAL as i32)).with_object(self.id));
         }
         Ok(())
     }
+
+    fn blob(&self) -> Arc<SendBlob> {
+        self.blob.clone().expect("blob is only taken on close")
+    }
+
+    fn parts(&self) -> (Arc<SendBlob>, Arc<IoChannel>) {
+        let channel = self
+            .channel
+            .clone()
+            .expect("channel is only taken on close");
+        (self.blob(), channel)
+    }
+}
+
+impl Drop for RemoteBlob {
+    fn drop(&mut self) {
+        // the blob closes itself on its thread, but the channel must be freed there too
+        if let Some(channel) = self.channel.take() {
+            if let Err(e) = self.handle.send(move || drop(channel)) {
+                error!("failed to free io channel: {}", e);
+            }
+        }
+    }
+}
+
+/// Blob of a `RemoteBlob`, which may move across threads but is only
+/// used on its owning thread and sent back there to be dropped.
+#[derive(Debug)]
+struct SendBlob(Option<Blob<'static>>);
+
+unsafe impl Send for SendBlob {}
+unsafe impl Sync for SendBlob {}
+
+impl SendBlob {
+    /// Get the blob, failing off its owning thread.
+    fn get(&self) -> Result<&Blob<'static>> {
+        let blob = self.0.as_ref().expect("blob is only taken on close");
+        if blob.inner.thread != unsafe { spdk_get_thread() } {
+            return Err(SpdkError::from(-(EINVAL as i32))
+                .with_op("access blob off its thread")
+                .with_object(self.0.as_ref().map(|b| b.inner.ptr as usize).unwrap_or(0)));
+        }
+        Ok(blob)
+    }
+
+    fn into_inner(mut self) -> Result<Blob<'static>> {
+        self.get()?;
+        Ok(self.0.take().expect("blob is only taken on close"))
+    }
+}
+
+impl Drop for SendBlob {
+    fn drop(&mut self) {
+        let blob = match self.0.take() {
+            Some(blob) => blob,
+            None => return,
+        };
+        let thread = blob.inner.thread;
+        if thread.is_null() || thread == unsafe { spdk_get_thread() } {
+            return;
+        }
+        // a box, so that a failed send leaks the blob instead of dropping it here
+        let blob = Box::into_raw(Box::new(SendBlob(Some(blob)))) as usize;
+        if let Err(e) = send_msg(thread, move || unsafe {
+            drop(Box::from_raw(blob as *mut SendBlob))
+        }) {
+            error!("failed to drop blob on its thread: {}", e);
+        }
+    }
+}
+
+/// Iterator over the allocated extents of a blob, see `Blob::allocated_extents`.
+#[derive(Debug)]
+pub struct AllocatedExtents<'a, 'bs> {
+    blob: &'a Blob<'bs>,
+    offset: u64,
+    end: u64,
+}
+
+impl Iterator for AllocatedExtents<'_, '_> {
+    type Item = Range<u64>;
+
+    fn next(&mut self) -> Option<Range<u64>> {
+        if self.offset >= self.end {
+            return None;
+        }
+        let ptr = self.blob.inner.ptr;
+        let start = unsafe { spdk_blob_get_next_allocated_io_unit(ptr, self.offset) };
+        if start >= self.end {
+            // `u64::MAX` when there is no allocated io unit left
+            self.offset = self.end;
+            return None;
+        }
+        let stop = unsafe { spdk_blob_get_next_unallocated_io_unit(ptr, start) }.min(self.end);
+        self.offset = stop;
+        Some(start..stop)
+    }
+}
+
+/// Close a blob without waiting for the result.
+extern "C" fn close_detached(blob: *mut c_void) {
+    unsafe {
+        spdk_blob_close(
+            blob as _,
+            Some(close_detached_callback),
+            std::ptr::null_mut(),
+        )
+    };
+}
+
+extern "C" fn close_detached_callback(_arg: *mut c_void, bserrno: c_int) {
+    if bserrno != 0 {
+        error!("close blob error: {}", bserrno);
+    }
+}
+
+fn xattr_name(name: &str) -> Result<CString> {
+    CString::new(name).map_err(|_| xattr_error("parse xattr name", name))
+}
+
+/// Encode an xattr value, which must fit in a `u16` length.
+fn encode_xattr<T: Serialize + ?Sized>(name: &str, value: &T) -> Result<Vec<u8>> {
+    let value = serde_json::to_vec(value).map_err(|_| xattr_error("encode xattr", name))?;
+    if value.len() > u16::MAX as usize {
+        return Err(SpdkError::from(-(ERANGE as i32))
+            .with_op("encode xattr")
+            .with_object(name));
+    }
+    Ok(value)
+}
+
+/// Look up an initial xattr of `BlobOpts` while the blob is created.
+extern "C" fn get_xattr_value(
+    ctx: *mut c_void,
+    name: *const c_char,
+    value: *mut *const c_void,
+    value_len: *mut u64,
+) {
+    let xattrs = unsafe { &*(ctx as *const XattrList) };
+    let name = unsafe { CStr::from_ptr(name) };
+    let found = xattrs.iter().find(|(n, _)| n.as_c_str() == name);
+    unsafe {
+        match found {
+            Some((_, v)) => {
+                *value = v.as_ptr() as _;
+                *value_len = v.len() as _;
+            }
+            None => {
+                *value = std::ptr::null();
+                *value_len = 0;
+            }
+        }
+    }
+}
+
+fn xattr_error(op: &str, name: &str) -> SpdkError {
+    SpdkError::from(-(EINVAL as i32))
+        .with_op(op)
+        .with_object(name)
 }
 
 extern "C" fn callback(arg: *mut c_void, bserrno: c_int) {
@@ -464,32 +1871,51 @@ extern "C" fn callback_with<T>(arg: *mut c_void, bs: T, bserrno: c_int) {
     complete.complete(result);
 }
 
-extern "C" fn init_callback(mut arg: *mut c_void, bs: *mut spdk_blob_store, bserrno: c_int) {
+/// Argument of `init_callback`, wrapping the `cb_arg` of `Blobstore::init_sync`
+/// and `Blobstore::load_sync` to record the blobstore on its device.
+struct InitSyncArg {
+    slot: *mut c_void,
+    blobstore: Rc<Cell<*mut spdk_blob_store>>,
+}
+
+extern "C" fn init_callback(arg: *mut c_void, bs: *mut spdk_blob_store, bserrno: c_int) {
     if bserrno != 0 {
         error!("bs error");
     }
     if bs.is_null() {
         error!("bs pointer is null");
     }
-    let (bs_, n) = unsafe { *Box::from_raw(arg as *mut (Arc<Mutex<Blobstore>>, Arc<Notify>)) };
+    let arg = unsafe { Box::from_raw(arg as *mut InitSyncArg) };
+    arg.blobstore.set(bs);
+    let (bs_, n) = unsafe { *Box::from_raw(arg.slot as *mut (Arc<Mutex<Blobstore>>, Arc<Notify>)) };
     unsafe {
         bs_.lock().unwrap().ptr = bs;
         n.notify_one();
     }
 }
 
-extern "C" fn open_callback(mut arg: *mut c_void, blob: *mut spdk_blob, bserrno: c_int) {
+/// Argument of `open_callback`, wrapping the `cb_arg` of `Blobstore::open_blob_sync`.
+struct OpenSyncArg {
+    slot: *mut c_void,
+    bs: *mut spdk_blob_store,
+    io_unit_size: u64,
+}
+
+extern "C" fn open_callback(arg: *mut c_void, blob: *mut spdk_blob, bserrno: c_int) {
     if bserrno != 0 {
         error!("open error");
     }
     if blob.is_null() {
         error!("open blob pointer null");
     }
-    let (blob_, n) = unsafe { *Box::from_raw(arg as *mut (Arc<Mutex<Blob>>, Arc<Notify>)) };
-    unsafe {
-        blob_.lock().unwrap().ptr = blob;
-        n.notify_one();
+    let arg = unsafe { *Box::from_raw(arg as *mut OpenSyncArg) };
+    let (blob_, n) = unsafe {
+        *Box::from_raw(arg.slot as *mut (Arc<Mutex<Option<Blob<'static>>>>, Arc<Notify>))
+    };
+    if !blob.is_null() {
+        *blob_.lock().unwrap() = Some(Blob::from_raw(blob, arg.bs, arg.io_unit_size));
     }
+    n.notify_one();
 }
 
 extern "C" fn create_callback(mut arg: *mut c_void, blob_id: spdk_blob_id, bserrno: c_int) {
@@ -1,10 +1,8 @@
-use crate::{Result, SpdkError};
+use crate::bdev::{bdev_event_callback, BdevEvent, BdevEventCtx, BdevEventStream};
+use crate::{blob, Result, SpdkError};
 use log::*;
 use spdk_sys::*;
-use std::{
-    ffi::{c_void, CString},
-    mem::MaybeUninit,
-};
+use std::{cell::Cell, ffi::CString, mem::MaybeUninit, os::raw::c_void, rc::Rc};
 
 /// SPDK blob store block device.
 ///
@@ -13,6 +11,10 @@ use std::{
 #[derive(Debug)]
 pub struct BlobStoreBDev {
     pub(crate) ptr: *mut spdk_bs_dev,
+    events: Rc<BdevEventCtx>,
+    /// Blobstore loaded on this device, null before init or load.
+    blobstore: Rc<Cell<*mut spdk_blob_store>>,
+    auto_grow: Rc<Cell<bool>>,
 }
 
 impl BlobStoreBDev {
@@ -20,27 +22,170 @@ impl BlobStoreBDev {
     pub fn create(name: &str) -> Result<Self> {
         let cname = CString::new(name).expect("Couldn't create a string");
         let mut ptr = MaybeUninit::uninit();
-        extern "C" fn callback(
-            ty: spdk_bdev_event_type,
-            bdev: *mut spdk_bdev,
-            event_ctx: *mut c_void,
-        ) {
-            warn!(
-                "bdev callback: type={:?}, bdev={:?}, ctx={:?}",
-                ty, bdev, event_ctx
-            );
-        }
+        let events = BdevEventCtx::new();
+        // The bs_dev is owned by the blobstore once loaded and may outlive this handle,
+        // so the reference passed to SPDK is released when the bs_dev is destroyed.
+        let event_ctx = Rc::into_raw(events.clone());
         let err = unsafe {
             spdk_bdev_create_bs_dev_ext(
                 cname.as_ptr(),
-                Some(callback),
-                std::ptr::null_mut(),
+                Some(bdev_event_callback),
+                event_ctx as _,
                 ptr.as_mut_ptr(),
             )
         };
-        SpdkError::from_retval(err)?;
+        if let Err(e) = SpdkError::from_retval(err) {
+            unsafe { drop(Rc::from_raw(event_ctx)) };
+            return Err(e);
+        }
+        let wrapped = unsafe { wrap(ptr.assume_init(), event_ctx) };
+        let blobstore: Rc<Cell<*mut spdk_blob_store>> = Rc::new(Cell::new(std::ptr::null_mut()));
+        let auto_grow = Rc::new(Cell::new(false));
+        {
+            let blobstore = blobstore.clone();
+            let auto_grow = auto_grow.clone();
+            events.set_internal_handler(Box::new(move |event| {
+                if event != BdevEvent::Resize {
+                    return;
+                }
+                // events stop when the blobstore is unloaded and destroys the bs_dev
+                unsafe {
+                    spdk_bdev_update_bs_blockcnt((*wrapped).inner);
+                    (*wrapped).dev.blockcnt = (*(*wrapped).inner).blockcnt;
+                }
+                if auto_grow.get() && !blobstore.get().is_null() {
+                    blob::grow_detached(blobstore.get());
+                }
+            }));
+        }
         Ok(BlobStoreBDev {
-            ptr: unsafe { ptr.assume_init() },
+            ptr: unsafe { &mut (*wrapped).dev },
+            events,
+            blobstore,
+            auto_grow,
         })
     }
+
+    /// Subscribe to events of the underlying bdev.
+    ///
+    /// The stream ends after the bdev is removed.
+    pub fn events(&self) -> BdevEventStream {
+        self.events.subscribe()
+    }
+
+    /// Set a handler called on every event of the underlying bdev.
+    pub fn set_event_handler(&self, handler: impl FnMut(BdevEvent) + 'static) {
+        self.events.set_handler(Box::new(handler));
+    }
+
+    /// Grow the blobstore on this device whenever the bdev is resized.
+    ///
+    /// Disabled by default, in which case `Blobstore::grow` must be called
+    /// after a resize to use the new capacity.
+    pub fn set_auto_grow(&self, auto_grow: bool) {
+        self.auto_grow.set(auto_grow);
+    }
+
+    /// Record the blobstore initialized or loaded on this device.
+    pub(crate) fn set_blobstore(&self, bs: *mut spdk_blob_store) {
+        self.blobstore.set(bs);
+    }
+
+    /// Where the blobstore is recorded, for callbacks that outlive the borrow.
+    pub(crate) fn blobstore_slot(&self) -> Rc<Cell<*mut spdk_blob_store>> {
+        self.blobstore.clone()
+    }
+}
+
+/// bs_dev handed to SPDK, forwarding to the bs_dev of the bdev so that
+/// `destroy` finds the event context and the thread it belongs to.
+#[repr(C)]
+struct WrappedBsDev {
+    dev: spdk_bs_dev,
+    inner: *mut spdk_bs_dev,
+    events: *const BdevEventCtx,
+    thread: *mut spdk_thread,
+}
+
+/// Wrap `inner`, the returned bs_dev releases `events` when destroyed.
+unsafe fn wrap(inner: *mut spdk_bs_dev, events: *const BdevEventCtx) -> *mut WrappedBsDev {
+    let mut dev = *inner;
+    ops::forward(&mut dev);
+    dev.destroy = Some(destroy_bs_dev);
+    Box::into_raw(Box::new(WrappedBsDev {
+        dev,
+        inner,
+        events,
+        thread: spdk_get_thread(),
+    }))
+}
+
+unsafe extern "C" fn destroy_bs_dev(bs_dev: *mut spdk_bs_dev) {
+    let wrapped = bs_dev as *mut WrappedBsDev;
+    // no more events once the bs_dev and its descriptor are gone
+    if let Some(destroy) = (*(*wrapped).inner).destroy {
+        destroy((*wrapped).inner);
+    }
+    // the event context is not thread safe, release it where it was created
+    let thread = (*wrapped).thread;
+    if thread.is_null() || thread == spdk_get_thread() {
+        release_bs_dev(wrapped as _);
+        return;
+    }
+    let rc = spdk_thread_send_msg(thread, Some(release_bs_dev), wrapped as _);
+    if rc != 0 {
+        error!("failed to release bs_dev events: {}", rc);
+    }
+}
+
+unsafe extern "C" fn release_bs_dev(ctx: *mut c_void) {
+    let wrapped = Box::from_raw(ctx as *mut WrappedBsDev);
+    let events = Rc::from_raw(wrapped.events);
+    events.finish();
+}
+
+/// Operations of a `WrappedBsDev`, forwarded to the inner bs_dev.
+mod ops {
+    use super::WrappedBsDev;
+    use spdk_sys::*;
+    use std::os::raw::{c_int, c_void};
+
+    macro_rules! forward {
+        ($($op:ident($($arg:ident: $ty:ty),*) $(-> $ret:ty)?;)*) => {
+            $(
+                unsafe extern "C" fn $op(dev: *mut spdk_bs_dev $(, $arg: $ty)*) $(-> $ret)? {
+                    let inner = (*(dev as *mut WrappedBsDev)).inner;
+                    ((*inner).$op.unwrap())(inner $(, $arg)*)
+                }
+            )*
+
+            /// Point the operations the inner bs_dev implements at the forwarders.
+            pub(super) fn forward(dev: &mut spdk_bs_dev) {
+                $(
+                    if dev.$op.is_some() {
+                        dev.$op = Some($op);
+                    }
+                )*
+            }
+        };
+    }
+
+    forward! {
+        create_channel() -> *mut spdk_io_channel;
+        destroy_channel(channel: *mut spdk_io_channel);
+        read(channel: *mut spdk_io_channel, payload: *mut c_void, lba: u64, lba_count: u32, cb_args: *mut spdk_bs_dev_cb_args);
+        write(channel: *mut spdk_io_channel, payload: *mut c_void, lba: u64, lba_count: u32, cb_args: *mut spdk_bs_dev_cb_args);
+        readv(channel: *mut spdk_io_channel, iov: *mut iovec, iovcnt: c_int, lba: u64, lba_count: u32, cb_args: *mut spdk_bs_dev_cb_args);
+        writev(channel: *mut spdk_io_channel, iov: *mut iovec, iovcnt: c_int, lba: u64, lba_count: u32, cb_args: *mut spdk_bs_dev_cb_args);
+        readv_ext(channel: *mut spdk_io_channel, iov: *mut iovec, iovcnt: c_int, lba: u64, lba_count: u32, cb_args: *mut spdk_bs_dev_cb_args, ext_io_opts: *mut spdk_blob_ext_io_opts);
+        writev_ext(channel: *mut spdk_io_channel, iov: *mut iovec, iovcnt: c_int, lba: u64, lba_count: u32, cb_args: *mut spdk_bs_dev_cb_args, ext_io_opts: *mut spdk_blob_ext_io_opts);
+        flush(channel: *mut spdk_io_channel, cb_args: *mut spdk_bs_dev_cb_args);
+        write_zeroes(channel: *mut spdk_io_channel, lba: u64, lba_count: u64, cb_args: *mut spdk_bs_dev_cb_args);
+        unmap(channel: *mut spdk_io_channel, lba: u64, lba_count: u64, cb_args: *mut spdk_bs_dev_cb_args);
+        get_base_bdev() -> *mut spdk_bdev;
+        is_zeroes(lba: u64, lba_count: u64) -> bool;
+        translate_lba(lba: u64, base_lba: *mut u64) -> bool;
+        copy(channel: *mut spdk_io_channel, dst_lba: u64, src_lba: u64, lba_count: u64, cb_args: *mut spdk_bs_dev_cb_args);
+        is_degraded() -> bool;
+    }
 }
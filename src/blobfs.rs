@@ -2,38 +2,89 @@
 //!
 //! According to SPDK doc, only synchronous API is tested (except `init`, `load`, `unload`)
 
-use std::mem::MaybeUninit;
+use std::mem::{size_of, MaybeUninit};
 
-use crate::blob::IoChannel;
+use crate::blob::{BlobId, IoChannel};
 use crate::event::SpdkEvent;
 use crate::{blob_bdev::BlobStoreBDev, complete::LocalComplete, error::*};
+use futures_lite::io::{AsyncRead, AsyncSeek, AsyncWrite};
+use futures_lite::ready;
 use log::*;
 use spdk_sys::*;
-use std::ffi::{c_void, CString};
+use std::ffi::{c_void, CStr, CString};
+use std::io;
 use std::os::raw::c_int;
+use std::pin::Pin;
+use std::sync::mpsc;
+use std::task::{Context, Poll, Waker};
+use std::{cell::RefCell, rc::Rc};
+use tokio::sync::oneshot;
 
-#[derive(Debug, Clone)]
-pub struct SpdkFileStat {
-    ptr: *mut spdk_file_stat,
+/// Metadata of a blobfs file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FileStat {
+    blob_id: BlobId,
+    size: u64,
 }
 
-impl Default for SpdkFileStat {
-    fn default() -> Self {
-        Self {
-            ptr: std::ptr::null_mut(),
+impl FileStat {
+    /// Get the id of the blob backing the file.
+    pub fn blob_id(&self) -> BlobId {
+        self.blob_id
+    }
+
+    /// Get the file size in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl From<&spdk_file_stat> for FileStat {
+    fn from(stat: &spdk_file_stat) -> Self {
+        FileStat {
+            blob_id: BlobId::from_raw(stat.blobid),
+            size: stat.size,
         }
     }
 }
 
+/// A file listed by `SpdkFilesystem::list`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileEntry {
+    name: String,
+    length: u64,
+    blob_id: BlobId,
+}
+
+impl FileEntry {
+    /// Get the file name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the file length in bytes.
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// Get the id of the blob backing the file.
+    pub fn blob_id(&self) -> BlobId {
+        self.blob_id
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct SpdkFilesystem {
     pub ptr: *mut spdk_filesystem,
+    /// Metadata thread, which initialized or loaded the filesystem.
+    thread: *mut spdk_thread,
 }
 
 impl Default for SpdkFilesystem {
     fn default() -> Self {
         Self {
             ptr: std::ptr::null_mut(),
+            thread: std::ptr::null_mut(),
         }
     }
 }
@@ -60,7 +111,9 @@ impl SpdkFile {
     pub fn close(&self, ctx: &SpdkFsThreadCtx) -> Result<()> {
         let ret = unsafe { spdk_file_close(self.ptr, ctx.ptr) };
         if ret != 0 {
-            return Err(SpdkError::from(-1));
+            return Err(SpdkError::from(ret)
+                .with_op("close file")
+                .with_object(self.name().unwrap_or_default()));
         }
         Ok(())
     }
@@ -69,15 +122,22 @@ impl SpdkFile {
     pub fn truncate(&self, ctx: &SpdkFsThreadCtx, length: u64) -> Result<()> {
         let ret = unsafe { spdk_file_truncate(self.ptr, ctx.ptr, length) };
         if ret != 0 {
-            return Err(SpdkError::from(-1));
+            return Err(SpdkError::from(ret)
+                .with_op("truncate file")
+                .with_object(self.name().unwrap_or_default()));
         }
         Ok(())
     }
 
     /// Get file name
     pub fn name(&self) -> Result<String> {
-        let name = unsafe { spdk_file_get_name(self.ptr).as_ref().unwrap() };
-        Ok(name.to_string())
+        let name = unsafe { spdk_file_get_name(self.ptr) };
+        if name.is_null() {
+            return Err(SpdkError::from(-(EINVAL as i32)).with_op("get file name"));
+        }
+        Ok(unsafe { CStr::from_ptr(name) }
+            .to_string_lossy()
+            .into_owned())
     }
 
     /// Get file length
@@ -96,7 +156,9 @@ impl SpdkFile {
     ) -> Result<()> {
         let ret = unsafe { spdk_file_write(self.ptr, ctx.ptr, data.as_ptr() as _, offset, len) };
         if ret != 0 {
-            return Err(SpdkError::from(-1));
+            return Err(SpdkError::from(ret)
+                .with_op("write file")
+                .with_object(self.name().unwrap_or_default()));
         }
         Ok(())
     }
@@ -126,7 +188,9 @@ impl SpdkFile {
     pub fn sync(&mut self, ctx: &SpdkFsThreadCtx) -> Result<()> {
         let ret = unsafe { spdk_file_sync(self.ptr, ctx.ptr) };
         if ret != 0 {
-            return Err(SpdkError::from(-1));
+            return Err(SpdkError::from(ret)
+                .with_op("sync file")
+                .with_object(self.name().unwrap_or_default()));
         }
         Ok(())
     }
@@ -224,7 +288,7 @@ impl SpdkFilesystem {
             );
         })
         .await?;
-        Ok(SpdkFilesystem { ptr })
+        Ok(SpdkFilesystem::init_from_raw(ptr))
     }
 
     /// Init blobfs with no send_request function
@@ -233,7 +297,7 @@ impl SpdkFilesystem {
             spdk_fs_init(bs_dev.ptr, &mut opts.0, None, Some(callback_with), arg);
         })
         .await?;
-        Ok(SpdkFilesystem { ptr })
+        Ok(SpdkFilesystem::init_from_raw(ptr))
     }
 
     /// load blobfs from bs_dev
@@ -242,7 +306,7 @@ impl SpdkFilesystem {
             spdk_fs_load(bs_dev.ptr, Some(send_request_fn), Some(callback_with), arg);
         })
         .await?;
-        Ok(SpdkFilesystem { ptr })
+        Ok(SpdkFilesystem::init_from_raw(ptr))
     }
 
     /// unload blobfs
@@ -254,13 +318,17 @@ impl SpdkFilesystem {
         Ok(())
     }
 
-    pub async fn astat(&self, name: &str) -> Result<SpdkFileStat> {
-        let cname = CString::new(name).expect("Fail to parse name");
-        let ptr = do_async(|arg| unsafe {
-            spdk_fs_file_stat_async(self.ptr, cname.as_ptr(), Some(callback_with), arg);
+    /// Get the metadata of a file.
+    ///
+    /// Fails with `ErrorKind::NotFound` if the file does not exist.
+    pub async fn astat(&self, name: &str) -> Result<FileStat> {
+        let cname = file_name(name).with_op("stat file")?;
+        do_async(|arg| unsafe {
+            spdk_fs_file_stat_async(self.ptr, cname.as_ptr(), Some(stat_callback), arg);
         })
-        .await?;
-        Ok(SpdkFileStat { ptr })
+        .await
+        .with_op("stat file")
+        .with_object(name)
     }
 
     pub async fn acreate(&self, name: &str) -> Result<()> {
@@ -272,6 +340,7 @@ impl SpdkFilesystem {
         Ok(())
     }
 
+    /// Open file, see `OpenOptions` for a typed API.
     pub async fn aopen(&self, name: &str, flag: u32) -> Result<SpdkFile> {
         let cname = CString::new(name).expect("Fail to parse name");
         let ptr = do_async(|arg| unsafe {
@@ -321,14 +390,19 @@ impl SpdkFilesystem {
     pub fn alloc_io_channel(&self) -> Result<IoChannel> {
         let ptr = unsafe { spdk_fs_alloc_io_channel(self.ptr) };
         if ptr.is_null() {
-            return Err(SpdkError::from(-1));
+            return Err(SpdkError::from(-(ENOMEM as i32)).with_op("allocate io channel"));
         }
         Ok(IoChannel { ptr })
     }
 
     /// Initialize from raw pointer
+    ///
+    /// The current thread is taken as the metadata thread.
     pub fn init_from_raw(p: *mut spdk_filesystem) -> Self {
-        Self { ptr: p }
+        Self {
+            ptr: p,
+            thread: unsafe { spdk_get_thread() },
+        }
     }
 
     /// Free I/O channel from blobfs
@@ -345,16 +419,78 @@ impl SpdkFilesystem {
     pub fn alloc_thread_ctx(&self) -> Result<SpdkFsThreadCtx> {
         let ptr = unsafe { spdk_fs_alloc_thread_ctx(self.ptr) };
         if ptr.is_null() {
-            return Err(SpdkError::from(-1));
+            return Err(SpdkError::from(-(ENOMEM as i32)).with_op("allocate thread ctx"));
         }
         Ok(SpdkFsThreadCtx { ptr })
     }
 
-    pub fn stat(&self, ctx: &SpdkFsThreadCtx, name: &str, stat: &mut SpdkFileStat) -> Result<()> {
-        let cname = CString::new(name).expect("Fail to parse name");
-        let ret = unsafe { spdk_fs_file_stat(self.ptr, ctx.ptr, cname.as_ptr(), stat.ptr) };
+    /// Get the metadata of a file.
+    ///
+    /// Fails with `ErrorKind::NotFound` if the file does not exist.
+    pub fn stat(&self, ctx: &SpdkFsThreadCtx, name: &str) -> Result<FileStat> {
+        let cname = file_name(name).with_op("stat file")?;
+        let mut stat = MaybeUninit::<spdk_file_stat>::zeroed();
+        let ret =
+            unsafe { spdk_fs_file_stat(self.ptr, ctx.ptr, cname.as_ptr(), stat.as_mut_ptr()) };
         if ret != 0 {
-            return Err(SpdkError::from(-1));
+            return Err(SpdkError::from(ret).with_op("stat file").with_object(name));
+        }
+        Ok(FileStat::from(unsafe { stat.assume_init_ref() }))
+    }
+
+    /// List the files of the filesystem.
+    ///
+    /// The listing is a snapshot taken on the metadata thread, so files may be
+    /// created or deleted concurrently. Off SPDK threads this blocks like the
+    /// other sync APIs. On SPDK threads it fails with `EINVAL` unless called
+    /// on the metadata thread, which must not be blocked, use `alist` there.
+    pub fn list(&self) -> Result<std::vec::IntoIter<FileEntry>> {
+        let current = unsafe { spdk_get_thread() };
+        if !current.is_null() && current == self.thread {
+            return Ok(list_files(self.ptr).into_iter());
+        }
+        if !current.is_null() || self.thread.is_null() {
+            return Err(SpdkError::from(-(EINVAL as i32)).with_op("list files"));
+        }
+        let (tx, rx) = mpsc::channel();
+        self.send_list(move |files| {
+            // the caller is blocked on the receiver
+            let _ = tx.send(files);
+        })?;
+        rx.recv()
+            .map(Vec::into_iter)
+            .map_err(|_| SpdkError::from(-(ECANCELED as i32)).with_op("list files"))
+    }
+
+    /// List the files of the filesystem from any thread.
+    ///
+    /// Like `list`, but waits for the metadata thread without blocking.
+    pub async fn alist(&self) -> Result<std::vec::IntoIter<FileEntry>> {
+        let current = unsafe { spdk_get_thread() };
+        if !current.is_null() && current == self.thread {
+            return Ok(list_files(self.ptr).into_iter());
+        }
+        if self.thread.is_null() {
+            return Err(SpdkError::from(-(EINVAL as i32)).with_op("list files"));
+        }
+        let (tx, rx) = oneshot::channel();
+        self.send_list(move |files| {
+            // the caller may have given up waiting
+            let _ = tx.send(files);
+        })?;
+        rx.await
+            .map(Vec::into_iter)
+            .map_err(|_| SpdkError::from(-(ECANCELED as i32)).with_op("list files"))
+    }
+
+    /// List the files on the metadata thread and pass them to `reply` there.
+    fn send_list(&self, reply: impl FnOnce(Vec<FileEntry>) + Send + 'static) -> Result<()> {
+        let reply: ListReply = Box::new(reply);
+        let arg = Box::into_raw(Box::new((self.ptr as usize, reply)));
+        let rc = unsafe { spdk_thread_send_msg(self.thread, Some(list_request), arg as _) };
+        if rc != 0 {
+            unsafe { drop(Box::from_raw(arg)) };
+            return Err(SpdkError::from(rc).with_op("list files"));
         }
         Ok(())
     }
@@ -365,12 +501,16 @@ impl SpdkFilesystem {
         let fs = self.clone();
         let ret = unsafe { spdk_fs_create_file(fs.ptr, ctx.ptr, cname.as_ptr()) };
         if ret != 0 {
-            return Err(SpdkError::from(-1));
+            return Err(SpdkError::from(ret)
+                .with_op("create file")
+                .with_object(name));
         }
         Ok(())
     }
 
     /// Open file
+    ///
+    /// `flags` are raw `SPDK_BLOBFS_OPEN_*` flags, see `OpenOptions` for a typed API.
     pub fn open(
         &self,
         ctx: &SpdkFsThreadCtx,
@@ -382,7 +522,7 @@ impl SpdkFilesystem {
         let ret =
             unsafe { spdk_fs_open_file(self.ptr, ctx.ptr, cname.as_ptr(), flags, &mut file.ptr) };
         if ret != 0 {
-            return Err(SpdkError::from(-1));
+            return Err(SpdkError::from(ret).with_op("open file").with_object(name));
         }
         Ok(())
     }
@@ -393,7 +533,9 @@ impl SpdkFilesystem {
         let to = CString::new(to).expect("Fail to parse new name");
         let ret = unsafe { spdk_fs_rename_file(self.ptr, ctx.ptr, from.as_ptr(), to.as_ptr()) };
         if ret != 0 {
-            return Err(SpdkError::from(-1));
+            return Err(SpdkError::from(ret)
+                .with_op("rename file")
+                .with_object(from.to_string_lossy()));
         }
         Ok(())
     }
@@ -403,7 +545,9 @@ impl SpdkFilesystem {
         let cname = CString::new(name).expect("Fail to parse name");
         let ret = unsafe { spdk_fs_delete_file(self.ptr, ctx.ptr, cname.as_ptr()) };
         if ret != 0 {
-            return Err(SpdkError::from(-1));
+            return Err(SpdkError::from(ret)
+                .with_op("delete file")
+                .with_object(name));
         }
         Ok(())
     }
@@ -412,7 +556,7 @@ impl SpdkFilesystem {
     pub fn set_cache_size(&self, size: u64) -> Result<()> {
         let ret = unsafe { spdk_fs_set_cache_size(size) };
         if ret != 0 {
-            return Err(SpdkError::from(-1));
+            return Err(SpdkError::from(ret).with_op("set cache size"));
         }
         Ok(())
     }
@@ -423,6 +567,436 @@ impl SpdkFilesystem {
     }
 }
 
+/// Options for opening a blobfs file, like `std::fs::OpenOptions`.
+///
+/// Files are always opened for both reading and writing.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpenOptions {
+    create: bool,
+    create_new: bool,
+    truncate: bool,
+}
+
+impl OpenOptions {
+    /// Default options: open an existing file as is.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create the file if it does not exist.
+    pub fn create(mut self, create: bool) -> Self {
+        self.create = create;
+        self
+    }
+
+    /// Create the file, failing with `ErrorKind::AlreadyExists` if it exists.
+    ///
+    /// `create` and `truncate` are ignored when set.
+    pub fn create_new(mut self, create_new: bool) -> Self {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Truncate the file to length 0 once opened.
+    pub fn truncate(mut self, truncate: bool) -> Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Open a file with the sync API.
+    pub fn open(&self, fs: &SpdkFilesystem, ctx: &SpdkFsThreadCtx, name: &str) -> Result<SpdkFile> {
+        file_name(name).with_op("open file")?;
+        if self.create_new {
+            fs.create(ctx, name)?;
+        }
+        let mut file = SpdkFile::default();
+        fs.open(ctx, name, self.flags(), &mut file)?;
+        if self.truncate && !self.create_new {
+            if let Err(e) = file.truncate(ctx, 0) {
+                let _ = file.close(ctx);
+                return Err(e);
+            }
+        }
+        Ok(file)
+    }
+
+    /// Open a file with the async API.
+    pub async fn aopen(&self, fs: &SpdkFilesystem, name: &str) -> Result<SpdkFile> {
+        file_name(name).with_op("open file")?;
+        if self.create_new {
+            fs.acreate(name)
+                .await
+                .with_op("create file")
+                .with_object(name)?;
+        }
+        let file = fs
+            .aopen(name, self.flags())
+            .await
+            .with_op("open file")
+            .with_object(name)?;
+        if self.truncate && !self.create_new {
+            if let Err(e) = file.atruncate(0).await {
+                let _ = file.aclose().await;
+                return Err(e.with_op("truncate file").with_object(name));
+            }
+        }
+        Ok(file)
+    }
+
+    fn flags(&self) -> u32 {
+        if self.create && !self.create_new {
+            SPDK_BLOBFS_OPEN_CREATE
+        } else {
+            0
+        }
+    }
+}
+
+/// A blobfs file with a cursor, implementing `std::io` traits over the sync API.
+///
+/// Blobfs only appends, so writes must start at the end of the file.
+/// The file is flushed and closed on drop, errors are then only logged.
+#[derive(Debug)]
+pub struct BlobfsFile<'a> {
+    file: SpdkFile,
+    ctx: &'a SpdkFsThreadCtx,
+    pos: u64,
+}
+
+impl<'a> BlobfsFile<'a> {
+    /// Wrap an open file, with the cursor at the start.
+    pub fn new(file: SpdkFile, ctx: &'a SpdkFsThreadCtx) -> Self {
+        BlobfsFile { file, ctx, pos: 0 }
+    }
+
+    /// Get the underlying file.
+    pub fn get_ref(&self) -> &SpdkFile {
+        &self.file
+    }
+}
+
+impl io::Read for BlobfsFile<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len() as u64;
+        let ret = self.file.read(self.ctx, buf, self.pos, len)?;
+        if ret < 0 {
+            let err = SpdkError::from(ret as i32).with_op("read file");
+            return Err(err.into());
+        }
+        self.pos += ret as u64;
+        Ok(ret as usize)
+    }
+}
+
+impl io::Write for BlobfsFile<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(self.ctx, buf, self.pos, buf.len() as u64)?;
+        self.pos += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(self.file.sync(self.ctx)?)
+    }
+}
+
+impl io::Seek for BlobfsFile<'_> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            io::SeekFrom::Start(pos) => Some(pos),
+            io::SeekFrom::End(delta) => self.file.get_len()?.checked_add_signed(delta),
+            io::SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        };
+        self.pos = pos.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position")
+        })?;
+        Ok(self.pos)
+    }
+}
+
+impl Drop for BlobfsFile<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.file.sync(self.ctx) {
+            error!("failed to flush file: {}", e);
+        }
+        if let Err(e) = self.file.close(self.ctx) {
+            error!("failed to close file: {}", e);
+        }
+    }
+}
+
+/// A blobfs file with a cursor, implementing `futures::io` traits over the async API.
+///
+/// It owns an I/O channel of the filesystem. `poll_close` syncs and closes
+/// the file, otherwise it is closed on drop without waiting. An operation
+/// left by a dropped future is finished, and its result discarded, before
+/// the next one starts.
+#[derive(Debug)]
+pub struct AsyncBlobfsFile {
+    file: SpdkFile,
+    channel: Option<IoChannel>,
+    pos: u64,
+    state: Rc<RefCell<FileOpState>>,
+    pending: Option<FileOp>,
+    closed: bool,
+}
+
+/// Operation in flight of an `AsyncBlobfsFile`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileOp {
+    Read(usize),
+    Write(usize),
+    Sync,
+    Close,
+}
+
+/// Completion of the operation in flight, shared with its callback.
+#[derive(Debug, Default)]
+struct FileOpState {
+    /// Data of the read or write in flight, kept alive until it completes.
+    buf: Vec<u8>,
+    result: Option<c_int>,
+    waker: Option<Waker>,
+    /// Set when the file is dropped in the middle of an operation,
+    /// to close it and free the channel on completion.
+    release: Option<(SpdkFile, IoChannel)>,
+}
+
+impl AsyncBlobfsFile {
+    /// Wrap an open file, with the cursor at the start.
+    pub fn new(fs: &SpdkFilesystem, file: SpdkFile) -> Result<Self> {
+        Ok(AsyncBlobfsFile {
+            file,
+            channel: Some(fs.alloc_io_channel()?),
+            pos: 0,
+            state: Rc::new(RefCell::new(FileOpState::default())),
+            pending: None,
+            closed: false,
+        })
+    }
+
+    /// Get the underlying file.
+    pub fn get_ref(&self) -> &SpdkFile {
+        &self.file
+    }
+
+    fn start(&mut self, op: FileOp) {
+        let file = self.file.ptr;
+        let channel = self
+            .channel
+            .as_ref()
+            .map_or(std::ptr::null_mut(), |c| c.ptr);
+        let buf = {
+            let mut state = self.state.borrow_mut();
+            state.result = None;
+            state.buf.as_mut_ptr() as *mut c_void
+        };
+        let arg = Rc::into_raw(self.state.clone()) as *mut c_void;
+        let cb: spdk_file_op_complete = Some(file_op_callback);
+        self.pending = Some(op);
+        // the callback may run before these return
+        unsafe {
+            match op {
+                FileOp::Read(len) => {
+                    spdk_file_read_async(file, channel, buf, self.pos, len as u64, cb, arg)
+                }
+                FileOp::Write(len) => {
+                    spdk_file_write_async(file, channel, buf, self.pos, len as u64, cb, arg)
+                }
+                FileOp::Sync => spdk_file_sync_async(file, channel, cb, arg),
+                FileOp::Close => spdk_file_close_async(file, cb, arg),
+            }
+        }
+    }
+
+    /// Poll the operation in flight, which must be started.
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<FileOp>> {
+        let mut state = self.state.borrow_mut();
+        let rc = match state.result.take() {
+            Some(rc) => rc,
+            None => {
+                state.waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+        };
+        let op = self.pending.take().expect("no operation in flight");
+        let op_name = match op {
+            FileOp::Read(_) => "read file",
+            FileOp::Write(_) => "write file",
+            FileOp::Sync => "sync file",
+            FileOp::Close => "close file",
+        };
+        SpdkError::from_retval(rc).with_op(op_name)?;
+        Poll::Ready(Ok(op))
+    }
+
+    /// Wait for an operation in flight that `resume` does not take over.
+    ///
+    /// Its future was dropped, so nobody sees its result.
+    fn poll_retire(
+        &mut self,
+        cx: &mut Context<'_>,
+        resume: impl Fn(FileOp) -> bool,
+    ) -> Poll<io::Result<()>> {
+        if self.closed {
+            return Poll::Ready(Err(SpdkError::from(-(EBADF as i32)).into()));
+        }
+        if let Some(op) = self.pending {
+            if !resume(op) {
+                let _ = ready!(self.poll_pending(cx));
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncRead for AsyncBlobfsFile {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_retire(cx, |op| matches!(op, FileOp::Read(_))))?;
+        if this.pending.is_none() {
+            // blobfs fails reads past the end of the file
+            let len = this.file.get_len()?.saturating_sub(this.pos);
+            let len = len.min(buf.len() as u64) as usize;
+            if len == 0 {
+                return Poll::Ready(Ok(0));
+            }
+            this.state.borrow_mut().buf.resize(len, 0);
+            this.start(FileOp::Read(len));
+        }
+        let len = match ready!(this.poll_pending(cx))? {
+            FileOp::Read(len) => len.min(buf.len()),
+            _ => unreachable!(),
+        };
+        buf[..len].copy_from_slice(&this.state.borrow().buf[..len]);
+        this.pos += len as u64;
+        Poll::Ready(Ok(len))
+    }
+}
+
+impl AsyncWrite for AsyncBlobfsFile {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        // only the same data may complete on behalf of this write
+        let same = matches!(this.pending, Some(FileOp::Write(_))) && this.state.borrow().buf == buf;
+        ready!(this.poll_retire(cx, |_| same))?;
+        if this.pending.is_none() {
+            if buf.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+            {
+                let mut state = this.state.borrow_mut();
+                state.buf.clear();
+                state.buf.extend_from_slice(buf);
+            }
+            this.start(FileOp::Write(buf.len()));
+        }
+        let len = match ready!(this.poll_pending(cx))? {
+            FileOp::Write(len) => len,
+            _ => unreachable!(),
+        };
+        this.pos += len as u64;
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_retire(cx, |op| op == FileOp::Sync))?;
+        if this.pending.is_none() {
+            this.start(FileOp::Sync);
+        }
+        ready!(this.poll_pending(cx))?;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.closed {
+            return Poll::Ready(Ok(()));
+        }
+        ready!(this.poll_retire(cx, |op| op == FileOp::Sync || op == FileOp::Close))?;
+        if this.pending.is_none() {
+            this.start(FileOp::Sync);
+        }
+        loop {
+            match ready!(this.poll_pending(cx))? {
+                FileOp::Sync => this.start(FileOp::Close),
+                _ => {
+                    this.closed = true;
+                    this.channel = None;
+                    return Poll::Ready(Ok(()));
+                }
+            }
+        }
+    }
+}
+
+impl AsyncSeek for AsyncBlobfsFile {
+    fn poll_seek(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        pos: io::SeekFrom,
+    ) -> Poll<io::Result<u64>> {
+        let this = self.get_mut();
+        ready!(this.poll_retire(cx, |_| false))?;
+        let pos = match pos {
+            io::SeekFrom::Start(pos) => Some(pos),
+            io::SeekFrom::End(delta) => this.file.get_len()?.checked_add_signed(delta),
+            io::SeekFrom::Current(delta) => this.pos.checked_add_signed(delta),
+        };
+        this.pos = pos.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position")
+        })?;
+        Poll::Ready(Ok(this.pos))
+    }
+}
+
+impl Drop for AsyncBlobfsFile {
+    fn drop(&mut self) {
+        if self.closed {
+            return;
+        }
+        let file = std::mem::take(&mut self.file);
+        let channel = self.channel.take();
+        match (self.pending, channel) {
+            (Some(FileOp::Close), _) => {}
+            // release them once the operation in flight completes
+            (Some(_), Some(channel)) => self.state.borrow_mut().release = Some((file, channel)),
+            _ => unsafe {
+                spdk_file_close_async(file.ptr, Some(close_callback), std::ptr::null_mut())
+            },
+        }
+    }
+}
+
+extern "C" fn file_op_callback(arg: *mut c_void, fserrno: c_int) {
+    let state = unsafe { Rc::from_raw(arg as *const RefCell<FileOpState>) };
+    let mut state = state.borrow_mut();
+    if let Some((file, channel)) = state.release.take() {
+        drop(channel);
+        unsafe { spdk_file_close_async(file.ptr, Some(close_callback), std::ptr::null_mut()) };
+        return;
+    }
+    state.result = Some(fserrno);
+    if let Some(waker) = state.waker.take() {
+        waker.wake();
+    }
+}
+
+extern "C" fn close_callback(_arg: *mut c_void, fserrno: c_int) {
+    if fserrno != 0 {
+        error!("close file error: {}", fserrno);
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SpdkFsThreadCtx {
     ptr: *mut spdk_fs_thread_ctx,
@@ -469,6 +1043,52 @@ extern "C" fn callback(arg: *mut c_void, fserrno: c_int) {
     callback_with(arg, (), fserrno);
 }
 
+type ListReply = Box<dyn FnOnce(Vec<FileEntry>) + Send>;
+
+extern "C" fn list_request(arg: *mut c_void) {
+    let (fs, reply) = unsafe { *Box::from_raw(arg as *mut (usize, ListReply)) };
+    reply(list_files(fs as _));
+}
+
+fn list_files(fs: *mut spdk_filesystem) -> Vec<FileEntry> {
+    let mut files = Vec::new();
+    let mut iter = unsafe { spdk_fs_iter_first(fs) };
+    while !iter.is_null() {
+        let file = SpdkFile {
+            ptr: unsafe { spdk_fs_iter_get_file(iter) },
+        };
+        let mut id: spdk_blob_id = 0;
+        unsafe {
+            spdk_file_get_id(
+                file.ptr,
+                &mut id as *mut spdk_blob_id as _,
+                size_of::<spdk_blob_id>() as _,
+            )
+        };
+        files.push(FileEntry {
+            name: file.name().unwrap_or_default(),
+            length: unsafe { spdk_file_get_length(file.ptr) },
+            blob_id: BlobId::from_raw(id),
+        });
+        iter = unsafe { spdk_fs_iter_next(iter) };
+    }
+    files
+}
+
+/// The stat is only valid during the callback, so copy it out.
+extern "C" fn stat_callback(arg: *mut c_void, stat: *mut spdk_file_stat, fserrno: c_int) {
+    let stat = if fserrno == 0 && !stat.is_null() {
+        FileStat::from(unsafe { &*stat })
+    } else {
+        FileStat::default()
+    };
+    callback_with(arg, stat, fserrno);
+}
+
+fn file_name(name: &str) -> Result<CString> {
+    CString::new(name).map_err(|_| SpdkError::from(-(EINVAL as i32)).with_object(name))
+}
+
 /// unload callback for unload_sync
 extern "C" fn unload_callback(_arg: *mut c_void, fserrno: c_int) {
     if fserrno != 0 {
@@ -11,8 +11,7 @@ impl CpuSet {
     pub fn new() -> Result<Self> {
         let ptr = unsafe { spdk_cpuset_alloc() };
         if ptr.is_null() {
-            // FIXME: proper error
-            return Err(SpdkError::from(-1));
+            return Err(SpdkError::from(-(ENOMEM as i32)).with_op("allocate cpuset"));
         }
         Ok(CpuSet { ptr })
     }
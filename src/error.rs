@@ -1,24 +1,174 @@
 use spdk_sys::*;
-use std::ffi::CStr;
+use std::{ffi::CStr, fmt};
 
-#[derive(Debug, thiserror::Error)]
-#[error("spdk error: {msg}")]
+/// Kind of an [`SpdkError`], derived from its errno.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// `EPERM`
+    PermissionDenied,
+    /// `ENOENT`
+    NotFound,
+    /// `EIO`
+    Io,
+    /// `EBADF`
+    BadDescriptor,
+    /// `EAGAIN`
+    Again,
+    /// `ENOMEM`
+    NoMemory,
+    /// `EACCES`
+    AccessDenied,
+    /// `EBUSY`
+    Busy,
+    /// `EEXIST`
+    AlreadyExists,
+    /// `ENODEV`
+    NoDevice,
+    /// `EINVAL`
+    InvalidArgument,
+    /// `ENOSPC`
+    NoSpace,
+    /// `ERANGE`
+    OutOfRange,
+    /// `ENAMETOOLONG`
+    NameTooLong,
+    /// `ENOTSUP`
+    Unsupported,
+    /// `ETIMEDOUT`
+    TimedOut,
+    /// `ECANCELED`
+    Canceled,
+    /// Any other errno.
+    Other(i32),
+}
+
+impl ErrorKind {
+    /// Get the kind of a (positive or negative) errno.
+    #[allow(non_upper_case_globals)]
+    pub fn from_errno(errno: i32) -> Self {
+        let errno = errno.unsigned_abs();
+        match errno {
+            EPERM => ErrorKind::PermissionDenied,
+            ENOENT => ErrorKind::NotFound,
+            EIO => ErrorKind::Io,
+            EBADF => ErrorKind::BadDescriptor,
+            EAGAIN => ErrorKind::Again,
+            ENOMEM => ErrorKind::NoMemory,
+            EACCES => ErrorKind::AccessDenied,
+            EBUSY => ErrorKind::Busy,
+            EEXIST => ErrorKind::AlreadyExists,
+            ENODEV => ErrorKind::NoDevice,
+            EINVAL => ErrorKind::InvalidArgument,
+            ENOSPC => ErrorKind::NoSpace,
+            ERANGE => ErrorKind::OutOfRange,
+            ENAMETOOLONG => ErrorKind::NameTooLong,
+            ENOTSUP => ErrorKind::Unsupported,
+            ETIMEDOUT => ErrorKind::TimedOut,
+            ECANCELED => ErrorKind::Canceled,
+            _ => ErrorKind::Other(errno as i32),
+        }
+    }
+}
+
+/// NVMe completion status of a failed bdev I/O.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NvmeStatus {
+    pub cdw0: u32,
+    /// Status code type.
+    pub sct: i32,
+    /// Status code.
+    pub sc: i32,
+}
+
+/// SCSI status of a failed bdev I/O.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScsiStatus {
+    /// Status code.
+    pub sc: i32,
+    /// Sense key.
+    pub sk: i32,
+    /// Additional sense code.
+    pub asc: i32,
+    /// Additional sense code qualifier.
+    pub ascq: i32,
+}
+
+/// Device status of a failed bdev I/O, in both NVMe and SCSI terms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoStatus {
+    pub nvme: NvmeStatus,
+    pub scsi: ScsiStatus,
+}
+
+impl fmt::Display for IoStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "nvme sct={:#x} sc={:#x}, scsi sc={:#x} sk={:#x} asc={:#x} ascq={:#x}",
+            self.nvme.sct, self.nvme.sc, self.scsi.sc, self.scsi.sk, self.scsi.asc, self.scsi.ascq
+        )
+    }
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
 pub struct SpdkError {
-    msg: String,
+    kind: ErrorKind,
     errno: i32,
+    msg: String,
+    op: Option<String>,
+    object: Option<String>,
+    io_status: Option<IoStatus>,
+}
+
+impl fmt::Display for SpdkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "spdk error: ")?;
+        match (&self.op, &self.object) {
+            (Some(op), Some(object)) => write!(f, "{} {}: ", op, object)?,
+            (Some(op), None) => write!(f, "{}: ", op)?,
+            (None, Some(object)) => write!(f, "{}: ", object)?,
+            (None, None) => {}
+        }
+        write!(f, "{}", self.msg)?;
+        if let Some(status) = &self.io_status {
+            write!(f, " ({})", status)?;
+        }
+        Ok(())
+    }
 }
 
 impl From<i32> for SpdkError {
+    /// Build an error from an errno. SPDK returns negative errnos, but
+    /// positive ones are accepted as well.
+    ///
+    /// 0 is not an error and is turned into `EIO`, use `from_retval`
+    /// for return values that may be 0.
     fn from(errno: i32) -> Self {
-        assert_ne!(errno, 0);
-        let cstr = unsafe { spdk_strerror(-errno) };
+        let errno = match errno {
+            0 => -(EIO as i32),
+            errno => -(errno.unsigned_abs().min(i32::MAX as u32) as i32),
+        };
         SpdkError {
-            msg: unsafe { CStr::from_ptr(cstr) }.to_str().unwrap().into(),
+            kind: ErrorKind::from_errno(errno),
             errno,
+            msg: strerror(errno),
+            op: None,
+            object: None,
+            io_status: None,
         }
     }
 }
 
+fn strerror(errno: i32) -> String {
+    let cstr = unsafe { spdk_strerror(-errno) };
+    if cstr.is_null() {
+        return format!("errno {}", -errno);
+    }
+    unsafe { CStr::from_ptr(cstr) }
+        .to_string_lossy()
+        .into_owned()
+}
+
 impl SpdkError {
     pub fn from_retval(errno: i32) -> Result<()> {
         if errno == 0 {
@@ -27,6 +177,92 @@ impl SpdkError {
             Err(SpdkError::from(errno))
         }
     }
+
+    /// Error for an operation that the target does not support.
+    pub fn unsupported(op: &str) -> Self {
+        SpdkError::from(-(ENOTSUP as i32)).with_op(op)
+    }
+
+    /// Error for a failed bdev I/O, keeping the device status.
+    pub(crate) fn io_failed(status: IoStatus) -> Self {
+        SpdkError {
+            io_status: Some(status),
+            ..SpdkError::from(-(EIO as i32))
+        }
+    }
+
+    /// Record the operation that failed.
+    pub fn with_op(mut self, op: impl Into<String>) -> Self {
+        self.op = Some(op.into());
+        self
+    }
+
+    /// Record the object (bdev, blob, file, ...) the operation failed on.
+    pub fn with_object(mut self, object: impl fmt::Display) -> Self {
+        self.object = Some(object.to_string());
+        self
+    }
+
+    /// Get the kind of this error.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Get the (negative) errno of this error.
+    pub fn errno(&self) -> i32 {
+        self.errno
+    }
+
+    /// Get the operation that failed, if recorded.
+    pub fn op(&self) -> Option<&str> {
+        self.op.as_deref()
+    }
+
+    /// Get the object the operation failed on, if recorded.
+    pub fn object(&self) -> Option<&str> {
+        self.object.as_deref()
+    }
+
+    /// Get the device status of a failed bdev I/O.
+    pub fn io_status(&self) -> Option<IoStatus> {
+        self.io_status
+    }
+
+    /// Whether the operation failed because the object does not exist.
+    pub fn is_not_found(&self) -> bool {
+        self.kind == ErrorKind::NotFound
+    }
+
+    /// Whether the operation failed because it is not supported.
+    pub fn is_unsupported(&self) -> bool {
+        self.kind == ErrorKind::Unsupported
+    }
+}
+
+impl From<SpdkError> for std::io::Error {
+    fn from(err: SpdkError) -> Self {
+        let kind = std::io::Error::from_raw_os_error(-err.errno).kind();
+        std::io::Error::new(kind, err)
+    }
+}
+
+/// Attach operation context to the error of a [`Result`].
+pub trait ResultExt<T> {
+    /// Record the operation that failed.
+    fn with_op(self, op: &str) -> Result<T>;
+
+    /// Record the object the operation failed on.
+    fn with_object(self, object: impl fmt::Display) -> Result<T>;
+}
+
+impl<T> ResultExt<T> for Result<T> {
+    fn with_op(self, op: &str) -> Result<T> {
+        self.map_err(|e| e.with_op(op))
+    }
+
+    fn with_object(self, object: impl fmt::Display) -> Result<T> {
+        self.map_err(|e| e.with_object(object))
+    }
 }
 
 pub type Result<T> = std::result::Result<T, SpdkError>;
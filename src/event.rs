@@ -199,7 +199,7 @@ impl SpdkEvent {
     pub fn alloc(lcore: u32, arg1: *mut c_void, arg2: *mut c_void) -> Result<Self> {
         let ptr = unsafe { spdk_event_allocate(lcore, Some(callback2), arg1, arg2) };
         if ptr.is_null() {
-            return Err(SpdkError::from(-1));
+            return Err(SpdkError::from(-(ENOMEM as i32)).with_op("allocate event"));
         }
         Ok(SpdkEvent { ptr })
     }
@@ -1,4 +1,8 @@
 pub mod bdev;
+pub mod bdev_aio;
+pub mod bdev_malloc;
+pub mod bdev_module;
+pub mod bdev_null;
 pub mod blob;
 pub mod blob_bdev;
 pub mod blobfs;
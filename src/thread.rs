@@ -37,8 +37,7 @@ impl Poller {
             spdk_poller_register(Some(poller_wrapper::<F>), &*closure as *const F as _, 0)
         };
         if ptr.is_null() {
-            // FIXME: proper error
-            return Err(SpdkError::from(-1));
+            return Err(SpdkError::from(-(ENOMEM as i32)).with_op("register poller"));
         }
         Ok(Poller { ptr, closure })
     }
@@ -83,8 +82,9 @@ impl Thread {
         let cname = CString::new(name).expect("Couldn't create a string");
         let ptr = unsafe { spdk_thread_create(cname.as_ptr(), cpumask.ptr) };
         if ptr.is_null() {
-            // FIXME: proper error
-            return Err(SpdkError::from(-1));
+            return Err(SpdkError::from(-(ENOMEM as i32))
+                .with_op("create thread")
+                .with_object(name));
         }
         Ok(Thread { ptr })
     }